@@ -0,0 +1,174 @@
+//! Measures emulated instructions per second through the HAL.
+//!
+//! `cargo run --release --example hal_throughput [rom] [steps]`
+//!
+//! Without a ROM it runs a small assembled loop that keeps writing display
+//! memory, so the LCD callbacks fire alongside the timestamp ones.
+//!
+//! It then times the two ways a callback can reach HAL state, outside
+//! tamalib: through a thread-local context pointer as the HAL does now, and
+//! through the process-wide `OnceLock<Mutex<_>>` the HAL used before each
+//! engine owned its context (`old_hal` below is that code, trimmed to the
+//! timestamp and LCD callbacks). For the old end-to-end rate, run this
+//! example on the commit before `src/sys/hal.rs` moved to per-engine
+//! contexts.
+
+use std::cell::Cell;
+use std::hint::black_box;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use termatama::{asm, load_rom_words, ClockMode, TamaEngine};
+
+const DISPLAY_LOOP: &str = "
+    .org 0x100
+    LD   A,0xE
+    LD   XP,A
+loop:
+    LD   X,0x00
+    LDPX MX,0xF
+    LDPX MX,0x0
+    LDPX MX,0xA
+    LDPX MX,0x5
+    JP   loop
+";
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let words = match args.next() {
+        Some(path) => load_rom_words(&PathBuf::from(&path)).unwrap_or_else(|err| {
+            eprintln!("failed to load {path}: {err}");
+            std::process::exit(1);
+        }),
+        None => asm::assemble(DISPLAY_LOOP).expect("benchmark program"),
+    };
+    let steps: usize = args
+        .next()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(20_000_000);
+
+    for clock in [ClockMode::Wall, ClockMode::Virtual] {
+        let mut engine = TamaEngine::with_clock(words.clone(), clock).expect("engine init");
+        let start = Instant::now();
+        engine.tick_many(steps);
+        let elapsed = start.elapsed().as_secs_f64();
+        let emulated = engine.emulated_ticks() as f64 / termatama::sys::TICK_FREQUENCY as f64;
+        println!(
+            "{clock:?}: {steps} instructions in {elapsed:.3}s = {:.2} M instr/s ({:.0}x real time)",
+            steps as f64 / elapsed / 1e6,
+            emulated / elapsed
+        );
+    }
+
+    let calls = steps as u64;
+    let old = time_callbacks(calls, |x| {
+        black_box(old_hal::rs_hal_get_timestamp());
+        old_hal::rs_hal_set_lcd_matrix(x, 0, 1);
+    });
+    let mut state = new_hal::HalState::default();
+    new_hal::ACTIVE.with(|active| active.set(&mut state));
+    let new = time_callbacks(calls, |x| {
+        black_box(new_hal::rs_hal_get_timestamp());
+        new_hal::rs_hal_set_lcd_matrix(x, 0, 1);
+    });
+    new_hal::ACTIVE.with(|active| active.set(std::ptr::null_mut()));
+    println!(
+        "callbacks: {:.1} ns per timestamp + LCD pair through a thread-local context, \
+         {:.1} ns through the old global mutex ({:.2}x)",
+        new,
+        old,
+        old / new
+    );
+}
+
+/// Nanoseconds per call of `callback` over `calls` calls.
+fn time_callbacks(calls: u64, mut callback: impl FnMut(u8)) -> f64 {
+    let start = Instant::now();
+    for i in 0..calls {
+        callback((i % 32) as u8);
+    }
+    start.elapsed().as_nanos() as f64 / calls as f64
+}
+
+/// The HAL's state access before per-engine contexts.
+mod old_hal {
+    use std::sync::{Mutex, OnceLock};
+    use std::time::Instant;
+
+    struct HalState {
+        start: Instant,
+        lcd: [[bool; 32]; 16],
+    }
+
+    static HAL_STATE: OnceLock<Mutex<HalState>> = OnceLock::new();
+
+    fn state() -> &'static Mutex<HalState> {
+        HAL_STATE.get_or_init(|| {
+            Mutex::new(HalState {
+                start: Instant::now(),
+                lcd: [[false; 32]; 16],
+            })
+        })
+    }
+
+    pub fn rs_hal_get_timestamp() -> u32 {
+        let elapsed = state()
+            .lock()
+            .expect("hal lock")
+            .start
+            .elapsed()
+            .as_micros();
+        (elapsed.min(u32::MAX as u128)) as u32
+    }
+
+    pub fn rs_hal_set_lcd_matrix(x: u8, y: u8, val: u8) {
+        if (x as usize) < 32 && (y as usize) < 16 {
+            let mut guard = state().lock().expect("hal lock");
+            guard.lcd[y as usize][x as usize] = val != 0;
+        }
+    }
+}
+
+/// The same two callbacks reaching state the way `src/sys/hal.rs` does now.
+mod new_hal {
+    use super::Cell;
+    use std::time::Instant;
+
+    pub struct HalState {
+        start: Instant,
+        lcd: [[bool; 32]; 16],
+    }
+
+    impl Default for HalState {
+        fn default() -> Self {
+            Self {
+                start: Instant::now(),
+                lcd: [[false; 32]; 16],
+            }
+        }
+    }
+
+    thread_local! {
+        pub static ACTIVE: Cell<*mut HalState> = const { Cell::new(std::ptr::null_mut()) };
+    }
+
+    fn with_active<R>(default: R, f: impl FnOnce(&mut HalState) -> R) -> R {
+        let ptr = ACTIVE.with(Cell::get);
+        if ptr.is_null() {
+            return default;
+        }
+        f(unsafe { &mut *ptr })
+    }
+
+    pub fn rs_hal_get_timestamp() -> u32 {
+        with_active(0, |s| {
+            (s.start.elapsed().as_micros().min(u32::MAX as u128)) as u32
+        })
+    }
+
+    pub fn rs_hal_set_lcd_matrix(x: u8, y: u8, val: u8) {
+        if (x as usize) < 32 && (y as usize) < 16 {
+            with_active((), |s| s.lcd[y as usize][x as usize] = val != 0);
+        }
+    }
+}
@@ -0,0 +1,448 @@
+//! E0C6S46 assembler producing the word vectors `TamaEngine::new` accepts.
+//!
+//! The syntax is what `disasm` prints, plus:
+//!
+//! - `; comment` to end of line, `label:` before or on an instruction line
+//! - `.org ADDR` moves the output position, `.word V[,V...]` emits raw words,
+//!   `.fill COUNT[,V]` repeats a word and `.equ NAME,VALUE` defines a constant
+//! - numbers are decimal, `0x` hex or `0b` binary; operands may add or
+//!   subtract symbols and numbers (`table+2`)
+//!
+//! Jump and call operands that reference a label are checked against the page
+//! the CPU will use (the current page, or the one set by a `PSET` right
+//! before); `PSET label` selects the label's page.
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+use crate::disasm::{Arg, OpSpec, OPS, REG_NAMES};
+use crate::rom::{encode_rom, RomEncoding, ROM_WORDS};
+
+/// Words are padded with `JP 0x00`, so stray execution spins in place.
+pub const FILL_WORD: u16 = 0x000;
+
+const RESERVED: [&str; 20] = [
+    "A", "B", "MX", "MY", "X", "Y", "XP", "XH", "XL", "YP", "YH", "YL", "SP", "SPH", "SPL", "F",
+    "C", "NC", "Z", "NZ",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl AsmError {
+    fn new(line: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for AsmError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// Assembles `source` into a full-size program ROM (`ROM_WORDS` long, or
+/// longer if the source places code beyond it).
+pub fn assemble(source: &str) -> Result<Vec<u16>, AsmError> {
+    let items = parse(source)?;
+    let symbols = collect_symbols(&items)?;
+    emit(&items, &symbols)
+}
+
+pub fn assemble_to(source: &str, encoding: RomEncoding) -> Result<Vec<u8>, AsmError> {
+    Ok(encode_rom(&assemble(source)?, encoding))
+}
+
+enum Body<'a> {
+    Org(&'a str),
+    Equ(&'a str, &'a str),
+    Words(Vec<&'a str>),
+    Fill(&'a str, Option<&'a str>),
+    Op(String, Vec<&'a str>),
+}
+
+struct Item<'a> {
+    line: usize,
+    labels: Vec<&'a str>,
+    body: Option<Body<'a>>,
+}
+
+fn parse(source: &str) -> Result<Vec<Item<'_>>, AsmError> {
+    let mut items = Vec::new();
+    for (idx, raw) in source.lines().enumerate() {
+        let line = idx + 1;
+        let mut text = raw.split(';').next().unwrap_or("").trim();
+
+        let mut labels = Vec::new();
+        while let Some((name, rest)) = text.split_once(':') {
+            let name = name.trim();
+            if !is_identifier(name) {
+                break;
+            }
+            labels.push(name);
+            text = rest.trim();
+        }
+
+        let body = if text.is_empty() {
+            None
+        } else {
+            let (head, rest) = text
+                .split_once(char::is_whitespace)
+                .map_or((text, ""), |(h, r)| (h, r.trim()));
+            let operands: Vec<&str> = if rest.is_empty() {
+                Vec::new()
+            } else {
+                rest.split(',').map(str::trim).collect()
+            };
+
+            Some(match head.to_ascii_lowercase().as_str() {
+                ".org" => Body::Org(single(line, head, &operands)?),
+                ".equ" => match operands.as_slice() {
+                    [name, value] if is_identifier(name) => Body::Equ(name, value),
+                    _ => return Err(AsmError::new(line, ".equ expects NAME,VALUE")),
+                },
+                ".word" if !operands.is_empty() => Body::Words(operands),
+                ".fill" => match operands.as_slice() {
+                    [count] => Body::Fill(count, None),
+                    [count, value] => Body::Fill(count, Some(value)),
+                    _ => return Err(AsmError::new(line, ".fill expects COUNT[,VALUE]")),
+                },
+                directive if directive.starts_with('.') => {
+                    return Err(AsmError::new(line, format!("unknown directive {head}")));
+                }
+                _ => Body::Op(head.to_ascii_uppercase(), operands),
+            })
+        };
+
+        items.push(Item { line, labels, body });
+    }
+    Ok(items)
+}
+
+fn single<'a>(line: usize, head: &str, operands: &[&'a str]) -> Result<&'a str, AsmError> {
+    match operands {
+        [value] => Ok(value),
+        _ => Err(AsmError::new(line, format!("{head} expects one operand"))),
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Symbol {
+    Label(u16),
+    Const(i64),
+}
+
+fn collect_symbols(items: &[Item<'_>]) -> Result<HashMap<String, Symbol>, AsmError> {
+    let mut symbols = HashMap::new();
+    let mut pc: usize = 0;
+    for item in items {
+        for label in &item.labels {
+            define(&mut symbols, item.line, label, Symbol::Label(pc as u16))?;
+        }
+        match &item.body {
+            None => {}
+            Some(Body::Org(expr)) => {
+                pc = address(item.line, eval(item.line, expr, &symbols)?.0)? as usize
+            }
+            Some(Body::Equ(name, expr)) => {
+                let (value, _) = eval(item.line, expr, &symbols)?;
+                define(&mut symbols, item.line, name, Symbol::Const(value))?;
+            }
+            Some(Body::Words(words)) => pc += words.len(),
+            Some(Body::Fill(count, _)) => pc += count_of(item.line, count, &symbols)?,
+            Some(Body::Op(..)) => pc += 1,
+        }
+        address(item.line, pc as i64)?;
+    }
+    Ok(symbols)
+}
+
+fn define(
+    symbols: &mut HashMap<String, Symbol>,
+    line: usize,
+    name: &str,
+    symbol: Symbol,
+) -> Result<(), AsmError> {
+    if RESERVED.iter().any(|r| r.eq_ignore_ascii_case(name)) {
+        return Err(AsmError::new(line, format!("{name} is a register name")));
+    }
+    if symbols.insert(name.to_string(), symbol).is_some() {
+        return Err(AsmError::new(line, format!("{name} is defined twice")));
+    }
+    Ok(())
+}
+
+fn emit(items: &[Item<'_>], symbols: &HashMap<String, Symbol>) -> Result<Vec<u16>, AsmError> {
+    let mut out = vec![FILL_WORD; ROM_WORDS];
+    let mut pc: usize = 0;
+    let mut pset: Option<u16> = None;
+
+    for item in items {
+        let line = item.line;
+        let mut next_pset = None;
+        match &item.body {
+            None => continue,
+            Some(Body::Org(expr)) => pc = address(line, eval(line, expr, symbols)?.0)? as usize,
+            Some(Body::Equ(..)) => continue,
+            Some(Body::Words(words)) => {
+                for expr in words {
+                    let word = fit(line, eval(line, expr, symbols)?.0, 12)?;
+                    put(&mut out, &mut pc, word);
+                }
+            }
+            Some(Body::Fill(count, value)) => {
+                let count = count_of(line, count, symbols)?;
+                let word = match value {
+                    Some(expr) => fit(line, eval(line, expr, symbols)?.0, 12)?,
+                    None => FILL_WORD,
+                };
+                for _ in 0..count {
+                    put(&mut out, &mut pc, word);
+                }
+            }
+            Some(Body::Op(mnemonic, operands)) => {
+                let (spec, word) = encode(line, pc as u16, pset, mnemonic, operands, symbols)?;
+                if spec.args == [Arg::Page] {
+                    next_pset = Some(word & 0x1F);
+                }
+                put(&mut out, &mut pc, word);
+            }
+        }
+        pset = next_pset;
+    }
+    Ok(out)
+}
+
+fn put(out: &mut Vec<u16>, pc: &mut usize, word: u16) {
+    if *pc >= out.len() {
+        out.resize(*pc + 1, FILL_WORD);
+    }
+    out[*pc] = word;
+    *pc += 1;
+}
+
+fn encode(
+    line: usize,
+    pc: u16,
+    pset: Option<u16>,
+    mnemonic: &str,
+    operands: &[&str],
+    symbols: &HashMap<String, Symbol>,
+) -> Result<(&'static OpSpec, u16), AsmError> {
+    let mut first_error = None;
+    for spec in OPS
+        .iter()
+        .filter(|spec| spec.mnemonic == mnemonic && spec.args.len() == operands.len())
+    {
+        match encode_with(line, spec, pc, pset, operands, symbols) {
+            Ok(Some(word)) => return Ok((spec, word)),
+            Ok(None) => {}
+            Err(err) => {
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+
+    Err(first_error.unwrap_or_else(|| {
+        if OPS.iter().any(|spec| spec.mnemonic == mnemonic) {
+            AsmError::new(line, format!("invalid operands for {mnemonic}"))
+        } else {
+            AsmError::new(line, format!("unknown instruction {mnemonic}"))
+        }
+    }))
+}
+
+/// `Ok(None)` means the operands do not have this form's shape; errors are
+/// reserved for operands of the right shape with bad values.
+fn encode_with(
+    line: usize,
+    spec: &OpSpec,
+    pc: u16,
+    pset: Option<u16>,
+    operands: &[&str],
+    symbols: &HashMap<String, Symbol>,
+) -> Result<Option<u16>, AsmError> {
+    let mut word = spec.code;
+    for (arg, operand) in spec.args.iter().zip(operands) {
+        let upper = operand.to_ascii_uppercase();
+        let is_reserved = RESERVED.contains(&upper.as_str());
+        match *arg {
+            Arg::Lit(text) => {
+                if upper != text {
+                    return Ok(None);
+                }
+            }
+            Arg::Reg(shift) => match register(&upper) {
+                Some(code) => word |= code << shift,
+                None => return Ok(None),
+            },
+            Arg::RegTwice => match register(&upper) {
+                Some(code) => word |= code | (code << 2),
+                None => return Ok(None),
+            },
+            Arg::Mem => match memory_operand(&upper) {
+                Some(n) => word |= n,
+                None => return Ok(None),
+            },
+            Arg::Imm(shift, bits) => {
+                if is_reserved || memory_operand(&upper).is_some() {
+                    return Ok(None);
+                }
+                let (value, _) = eval(line, operand, symbols)?;
+                word |= fit(line, value, bits)? << shift;
+            }
+            Arg::Step => {
+                if is_reserved {
+                    return Ok(None);
+                }
+                let (value, uses_label) = eval(line, operand, symbols)?;
+                if uses_label {
+                    let target = address(line, value)?;
+                    let page = if spec.mnemonic == "CALZ" {
+                        (pc >> 8) & 0x10
+                    } else {
+                        pset.unwrap_or((pc >> 8) & 0x1F)
+                    };
+                    if target >> 8 != page {
+                        return Err(AsmError::new(
+                            line,
+                            format!(
+                                "{operand} (0x{target:04X}) is not on page 0x{page:02X}; \
+                                 add PSET {operand} before this instruction"
+                            ),
+                        ));
+                    }
+                    word |= target & 0xFF;
+                } else {
+                    word |= fit(line, value, 8)?;
+                }
+            }
+            Arg::Page => {
+                if is_reserved {
+                    return Ok(None);
+                }
+                let (value, uses_label) = eval(line, operand, symbols)?;
+                if uses_label {
+                    word |= (address(line, value)? >> 8) & 0x1F;
+                } else {
+                    word |= fit(line, value, 5)?;
+                }
+            }
+        }
+    }
+    Ok(Some(word))
+}
+
+fn register(upper: &str) -> Option<u16> {
+    REG_NAMES.iter().position(|r| *r == upper).map(|i| i as u16)
+}
+
+fn memory_operand(upper: &str) -> Option<u16> {
+    let digit = upper.strip_prefix('M')?;
+    if digit.len() != 1 {
+        return None;
+    }
+    u16::from_str_radix(digit, 16).ok()
+}
+
+/// Evaluates `a+b-c` style expressions; also reports whether a label (as
+/// opposed to a number or `.equ` constant) took part.
+fn eval(
+    line: usize,
+    expr: &str,
+    symbols: &HashMap<String, Symbol>,
+) -> Result<(i64, bool), AsmError> {
+    let expr = expr.trim();
+    if expr.is_empty() {
+        return Err(AsmError::new(line, "missing operand"));
+    }
+
+    let mut total = 0i64;
+    let mut uses_label = false;
+    let mut sign = 1i64;
+    let mut start = 0;
+    for (idx, c) in expr.char_indices().chain([(expr.len(), '+')]) {
+        if c != '+' && c != '-' {
+            continue;
+        }
+        let term = expr[start..idx].trim();
+        if !term.is_empty() {
+            total += sign * term_value(line, term, symbols, &mut uses_label)?;
+        } else if idx != 0 || c == '+' {
+            return Err(AsmError::new(line, format!("invalid expression {expr}")));
+        }
+        sign = if c == '-' { -1 } else { 1 };
+        start = idx + 1;
+    }
+    Ok((total, uses_label))
+}
+
+fn term_value(
+    line: usize,
+    term: &str,
+    symbols: &HashMap<String, Symbol>,
+    uses_label: &mut bool,
+) -> Result<i64, AsmError> {
+    let lower = term.to_ascii_lowercase();
+    let parsed = if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = lower.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2).ok()
+    } else if term.starts_with(|c: char| c.is_ascii_digit()) {
+        term.parse::<i64>().ok()
+    } else {
+        match symbols.get(term) {
+            Some(Symbol::Label(addr)) => {
+                *uses_label = true;
+                Some(*addr as i64)
+            }
+            Some(Symbol::Const(value)) => Some(*value),
+            None if is_identifier(term) => {
+                return Err(AsmError::new(line, format!("undefined symbol {term}")));
+            }
+            None => None,
+        }
+    };
+    parsed.ok_or_else(|| AsmError::new(line, format!("invalid number {term}")))
+}
+
+fn fit(line: usize, value: i64, bits: u8) -> Result<u16, AsmError> {
+    if value < 0 || value >= 1 << bits {
+        return Err(AsmError::new(
+            line,
+            format!("value {value} does not fit in {bits} bits"),
+        ));
+    }
+    Ok(value as u16)
+}
+
+fn address(line: usize, value: i64) -> Result<u16, AsmError> {
+    if !(0..=0x1FFF).contains(&value) {
+        return Err(AsmError::new(
+            line,
+            format!("address {value:#X} is out of range"),
+        ));
+    }
+    Ok(value as u16)
+}
+
+fn count_of(line: usize, expr: &str, symbols: &HashMap<String, Symbol>) -> Result<usize, AsmError> {
+    let (count, _) = eval(line, expr, symbols)?;
+    usize::try_from(count).map_err(|_| AsmError::new(line, format!("invalid count {count}")))
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '.')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
@@ -0,0 +1,267 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::sys::{SoundEvent, SoundEventKind, TICK_FREQUENCY};
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+const AMPLITUDE: i16 = 8_000;
+
+const HEADER_LEN: u32 = 36;
+const BYTES_PER_SAMPLE: u64 = 2;
+/// Most samples a RIFF file can hold: sizes are 32-bit.
+pub const MAX_SAMPLES: u64 = (u32::MAX - HEADER_LEN) as u64 / BYTES_PER_SAMPLE;
+
+/// Collects buzzer events and renders them as a square wave.
+pub struct WavRecorder {
+    sample_rate: u32,
+    oscillator_hz: u32,
+    start_tick: u64,
+    events: Vec<SoundEvent>,
+    /// Buzzer state after the last kept event.
+    frequency_dhz: u32,
+    playing: bool,
+}
+
+impl WavRecorder {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate: sample_rate.max(1),
+            oscillator_hz: TICK_FREQUENCY,
+            start_tick: 0,
+            events: Vec::new(),
+            frequency_dhz: 0,
+            playing: false,
+        }
+    }
+
+    /// Sets the engine oscillator rate the event ticks count at, from
+    /// `TamaEngine::oscillator_hz`. Defaults to `TICK_FREQUENCY`.
+    pub fn with_oscillator(mut self, oscillator_hz: u32) -> Self {
+        self.oscillator_hz = oscillator_hz.max(1);
+        self
+    }
+
+    /// Starts the recording at `tick` instead of 0, typically the engine's
+    /// `emulated_ticks()` when recording begins. Earlier events only set
+    /// the buzzer's initial state.
+    pub fn starting_at(mut self, tick: u64) -> Self {
+        self.start_tick = tick;
+        self
+    }
+
+    /// Keeps only the events that change what is heard: repeats of the
+    /// current state are dropped, and events before the start tick collapse
+    /// into the initial frequency and on/off state.
+    pub fn push_events<I: IntoIterator<Item = SoundEvent>>(&mut self, events: I) {
+        for event in events {
+            match event.kind {
+                SoundEventKind::Frequency(dhz) if dhz == self.frequency_dhz => continue,
+                SoundEventKind::Frequency(dhz) => self.frequency_dhz = dhz,
+                SoundEventKind::Start if self.playing => continue,
+                SoundEventKind::Stop if !self.playing => continue,
+                SoundEventKind::Start | SoundEventKind::Stop => self.playing = !self.playing,
+            }
+            if event.tick <= self.start_tick {
+                let is_frequency = matches!(event.kind, SoundEventKind::Frequency(_));
+                self.events
+                    .retain(|old| matches!(old.kind, SoundEventKind::Frequency(_)) != is_frequency);
+            }
+            self.events.push(event);
+        }
+    }
+
+    pub fn events(&self) -> &[SoundEvent] {
+        &self.events
+    }
+
+    /// Renders from the start tick up to `end_tick` as mono 16-bit samples.
+    pub fn render(&self, end_tick: u64) -> Vec<i16> {
+        self.samples(end_tick).collect()
+    }
+
+    /// Streams the recording into `out`. Fails before writing anything if
+    /// it is too long for a WAV file.
+    pub fn write_wav<W: Write>(&self, out: W, end_tick: u64) -> std::io::Result<()> {
+        let samples = self.samples(end_tick);
+        write_samples(out, samples.total, samples, self.sample_rate)
+    }
+
+    pub fn save(&self, path: &Path, end_tick: u64) -> std::io::Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        self.write_wav(file, end_tick)
+    }
+
+    fn samples(&self, end_tick: u64) -> Samples<'_> {
+        Samples::new(
+            &self.events,
+            self.start_tick,
+            end_tick,
+            self.sample_rate,
+            self.oscillator_hz,
+        )
+    }
+}
+
+impl Default for WavRecorder {
+    fn default() -> Self {
+        Self::new(DEFAULT_SAMPLE_RATE)
+    }
+}
+
+fn sample_count(start_tick: u64, end_tick: u64, sample_rate: u64, oscillator_hz: u64) -> u64 {
+    let ticks = end_tick.saturating_sub(start_tick) as u128;
+    (ticks * sample_rate as u128 / oscillator_hz as u128).min(u64::MAX as u128) as u64
+}
+
+fn too_long(samples: u64) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!("{samples} samples exceed the WAV limit of {MAX_SAMPLES}"),
+    )
+}
+
+/// Renders the ticks from `start_tick` up to `end_tick`. Events before
+/// `start_tick` set the buzzer's state at the first sample. Ticks count at
+/// `oscillator_hz`.
+pub fn synthesize(
+    events: &[SoundEvent],
+    start_tick: u64,
+    end_tick: u64,
+    sample_rate: u32,
+    oscillator_hz: u32,
+) -> Vec<i16> {
+    Samples::new(events, start_tick, end_tick, sample_rate, oscillator_hz).collect()
+}
+
+/// The square wave for a run of events, one sample at a time, so a long
+/// recording never has to sit in memory.
+struct Samples<'a> {
+    events: &'a [SoundEvent],
+    next_event: usize,
+    start_tick: u64,
+    sample_rate: u64,
+    oscillator_hz: u64,
+    n: u64,
+    total: u64,
+    frequency_dhz: u32,
+    playing: bool,
+    phase: f64,
+}
+
+impl<'a> Samples<'a> {
+    fn new(
+        events: &'a [SoundEvent],
+        start_tick: u64,
+        end_tick: u64,
+        sample_rate: u32,
+        oscillator_hz: u32,
+    ) -> Self {
+        let sample_rate = sample_rate.max(1) as u64;
+        let oscillator_hz = oscillator_hz.max(1) as u64;
+        Self {
+            events,
+            next_event: 0,
+            start_tick,
+            sample_rate,
+            oscillator_hz,
+            n: 0,
+            total: sample_count(start_tick, end_tick, sample_rate, oscillator_hz),
+            frequency_dhz: 0,
+            playing: false,
+            phase: 0.0,
+        }
+    }
+}
+
+impl Iterator for Samples<'_> {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if self.n == self.total {
+            return None;
+        }
+        let offset = self.n as u128 * self.oscillator_hz as u128 / self.sample_rate as u128;
+        let tick = self.start_tick.saturating_add(offset as u64);
+        self.n += 1;
+        while let Some(event) = self.events.get(self.next_event) {
+            if event.tick > tick {
+                break;
+            }
+            match event.kind {
+                SoundEventKind::Frequency(dhz) => self.frequency_dhz = dhz,
+                SoundEventKind::Start => self.playing = true,
+                SoundEventKind::Stop => self.playing = false,
+            }
+            self.next_event += 1;
+        }
+
+        if self.playing && self.frequency_dhz > 0 {
+            let hz = self.frequency_dhz as f64 / 10.0;
+            let sample = if self.phase < 0.5 {
+                AMPLITUDE
+            } else {
+                -AMPLITUDE
+            };
+            self.phase = (self.phase + hz / self.sample_rate as f64).fract();
+            Some(sample)
+        } else {
+            self.phase = 0.0;
+            Some(0)
+        }
+    }
+}
+
+pub fn write_wav<W: Write>(out: W, samples: &[i16], sample_rate: u32) -> std::io::Result<()> {
+    write_samples(
+        out,
+        samples.len() as u64,
+        samples.iter().copied(),
+        sample_rate,
+    )
+}
+
+/// Writes the header sized for `count` samples, then pulls that many from
+/// `samples` in chunks, so the caller never needs them all in memory.
+fn write_samples<W: Write>(
+    mut out: W,
+    count: u64,
+    samples: impl Iterator<Item = i16>,
+    sample_rate: u32,
+) -> std::io::Result<()> {
+    const CHANNELS: u16 = 1;
+    const BITS_PER_SAMPLE: u16 = 16;
+    const CHUNK_SAMPLES: usize = 4096;
+    let block_align = CHANNELS * BITS_PER_SAMPLE / 8;
+    let byte_rate = sample_rate * block_align as u32;
+    if count > MAX_SAMPLES {
+        return Err(too_long(count));
+    }
+    let data_len = (count * BYTES_PER_SAMPLE) as u32;
+
+    out.write_all(b"RIFF")?;
+    out.write_all(&(HEADER_LEN + data_len).to_le_bytes())?;
+    out.write_all(b"WAVE")?;
+
+    out.write_all(b"fmt ")?;
+    out.write_all(&16u32.to_le_bytes())?;
+    out.write_all(&1u16.to_le_bytes())?;
+    out.write_all(&CHANNELS.to_le_bytes())?;
+    out.write_all(&sample_rate.to_le_bytes())?;
+    out.write_all(&byte_rate.to_le_bytes())?;
+    out.write_all(&block_align.to_le_bytes())?;
+    out.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+
+    out.write_all(b"data")?;
+    out.write_all(&data_len.to_le_bytes())?;
+    let mut chunk = Vec::with_capacity(CHUNK_SAMPLES * BYTES_PER_SAMPLE as usize);
+    for sample in samples.take(count as usize) {
+        chunk.extend_from_slice(&sample.to_le_bytes());
+        if chunk.len() == chunk.capacity() {
+            out.write_all(&chunk)?;
+            chunk.clear();
+        }
+    }
+    out.write_all(&chunk)?;
+    out.flush()
+}
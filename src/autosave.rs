@@ -0,0 +1,76 @@
+//! Periodic saving while the pet runs, so a crash or a killed process
+//! loses at most one interval.
+
+use std::time::{Duration, Instant};
+
+use crate::TamaEngine;
+
+/// Interval used when none is configured.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Which clock an autosave interval is measured on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AutosaveClock {
+    /// Host time: saves at a steady rate whatever `--speed` is.
+    Wall,
+    /// The pet's time: saves after the same amount of pet life, so a
+    /// sped-up run saves more often in wall time.
+    Emulated,
+}
+
+impl AutosaveClock {
+    /// Name used by `--autosave-clock=`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Wall => "wall",
+            Self::Emulated => "emulated",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        [Self::Wall, Self::Emulated]
+            .into_iter()
+            .find(|clock| clock.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// Decides when the next periodic save is due. Saving itself is left to
+/// the caller, which reports back with [`Autosave::saved`].
+pub struct Autosave {
+    interval: Duration,
+    clock: AutosaveClock,
+    last_wall: Instant,
+    last_emulated: Duration,
+}
+
+impl Autosave {
+    /// A zero `interval` disables autosaving.
+    pub fn new(engine: &TamaEngine, interval: Duration, clock: AutosaveClock) -> Self {
+        Self {
+            interval,
+            clock,
+            last_wall: Instant::now(),
+            last_emulated: engine.emulated_time(),
+        }
+    }
+
+    pub fn due(&self, engine: &TamaEngine) -> bool {
+        if self.interval.is_zero() {
+            return false;
+        }
+        match self.clock {
+            AutosaveClock::Wall => self.last_wall.elapsed() >= self.interval,
+            AutosaveClock::Emulated => {
+                engine.emulated_time().saturating_sub(self.last_emulated) >= self.interval
+            }
+        }
+    }
+
+    /// Restarts the interval, after a periodic save or any other save.
+    /// Call it after a failed save too, so a full disk is retried once per
+    /// interval rather than on every loop iteration.
+    pub fn saved(&mut self, engine: &TamaEngine) {
+        self.last_wall = Instant::now();
+        self.last_emulated = engine.emulated_time();
+    }
+}
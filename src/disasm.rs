@@ -0,0 +1,330 @@
+//! E0C6S46 disassembler for the 12-bit words produced by `decode_rom`.
+//!
+//! Mnemonics follow the Epson manual. Operands are written with hex
+//! immediates (`0x1F`), `A`/`B`/`MX`/`MY` for the 2-bit register fields and
+//! `M0`..`MF` for the 4-bit memory operands, which is also what `asm` reads.
+
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+
+use Arg::{Imm, Lit, Mem, Page, Reg, RegTwice, Step};
+
+/// Names of the 2-bit `r`/`q` register field.
+pub const REG_NAMES: [&str; 4] = ["A", "B", "MX", "MY"];
+
+/// Address the CPU starts executing from after reset.
+pub const RESET_VECTOR: u16 = 0x100;
+
+/// Interrupt vectors on page 1, lowest priority last.
+pub const INTERRUPT_VECTORS: [(u16, &str); 6] = [
+    (0x102, "clock timer"),
+    (0x104, "stopwatch"),
+    (0x106, "K00-K03 input"),
+    (0x108, "K10-K13 input"),
+    (0x10A, "serial"),
+    (0x10C, "programmable timer"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arg {
+    /// Fixed operand text, e.g. the `X` in `LD X,e`.
+    Lit(&'static str),
+    /// 2-bit register field at the given shift.
+    Reg(u8),
+    /// `RLC r` encodes its register twice, at shifts 2 and 0.
+    RegTwice,
+    /// Unsigned immediate: shift and width in bits.
+    Imm(u8, u8),
+    /// `Mn` memory operand in the low nibble.
+    Mem,
+    /// 8-bit jump/call step within the page selected by `NP`.
+    Step,
+    /// 5-bit `NBP:NPP` page for `PSET`.
+    Page,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpSpec {
+    pub code: u16,
+    pub mask: u16,
+    pub mnemonic: &'static str,
+    pub args: &'static [Arg],
+}
+
+impl OpSpec {
+    pub fn matches(&self, word: u16) -> bool {
+        word & self.mask == self.code
+    }
+}
+
+const fn op(code: u16, mask: u16, mnemonic: &'static str, args: &'static [Arg]) -> OpSpec {
+    OpSpec {
+        code,
+        mask,
+        mnemonic,
+        args,
+    }
+}
+
+const M4: u16 = 0xF00;
+const M6: u16 = 0xFC0;
+const M7: u16 = 0xFE0;
+const M8: u16 = 0xFF0;
+const M10: u16 = 0xFFC;
+const M12: u16 = 0xFFF;
+
+/// The full instruction set. Fixed encodings that overlap a more general
+/// form (`INC X` is `LDPX A,A`, `SCF` is `SET F,0x1`, ...) come first so
+/// they win when decoding.
+pub const OPS: &[OpSpec] = &[
+    // Jumps, calls and returns
+    op(0x000, M4, "JP", &[Step]),
+    op(0x100, M4, "RETD", &[Imm(0, 8)]),
+    op(0x200, M4, "JP", &[Lit("C"), Step]),
+    op(0x300, M4, "JP", &[Lit("NC"), Step]),
+    op(0x400, M4, "CALL", &[Step]),
+    op(0x500, M4, "CALZ", &[Step]),
+    op(0x600, M4, "JP", &[Lit("Z"), Step]),
+    op(0x700, M4, "JP", &[Lit("NZ"), Step]),
+    op(0xE40, M7, "PSET", &[Page]),
+    op(0xFE8, M12, "JPBA", &[]),
+    op(0xFDF, M12, "RET", &[]),
+    op(0xFDE, M12, "RETS", &[]),
+    // System control
+    op(0xFF8, M12, "HALT", &[]),
+    op(0xFF9, M12, "SLP", &[]),
+    op(0xFFB, M12, "NOP5", &[]),
+    op(0xFFF, M12, "NOP7", &[]),
+    // Index registers
+    op(0x800, M4, "LD", &[Lit("Y"), Imm(0, 8)]),
+    op(0xB00, M4, "LD", &[Lit("X"), Imm(0, 8)]),
+    op(0xEE0, M12, "INC", &[Lit("X")]),
+    op(0xEF0, M12, "INC", &[Lit("Y")]),
+    op(0xE80, M10, "LD", &[Lit("XP"), Reg(0)]),
+    op(0xE84, M10, "LD", &[Lit("XH"), Reg(0)]),
+    op(0xE88, M10, "LD", &[Lit("XL"), Reg(0)]),
+    op(0xE90, M10, "LD", &[Lit("YP"), Reg(0)]),
+    op(0xE94, M10, "LD", &[Lit("YH"), Reg(0)]),
+    op(0xE98, M10, "LD", &[Lit("YL"), Reg(0)]),
+    op(0xEA0, M10, "LD", &[Reg(0), Lit("XP")]),
+    op(0xEA4, M10, "LD", &[Reg(0), Lit("XH")]),
+    op(0xEA8, M10, "LD", &[Reg(0), Lit("XL")]),
+    op(0xEB0, M10, "LD", &[Reg(0), Lit("YP")]),
+    op(0xEB4, M10, "LD", &[Reg(0), Lit("YH")]),
+    op(0xEB8, M10, "LD", &[Reg(0), Lit("YL")]),
+    op(0xA00, M8, "ADC", &[Lit("XH"), Imm(0, 4)]),
+    op(0xA10, M8, "ADC", &[Lit("XL"), Imm(0, 4)]),
+    op(0xA20, M8, "ADC", &[Lit("YH"), Imm(0, 4)]),
+    op(0xA30, M8, "ADC", &[Lit("YL"), Imm(0, 4)]),
+    op(0xA40, M8, "CP", &[Lit("XH"), Imm(0, 4)]),
+    op(0xA50, M8, "CP", &[Lit("XL"), Imm(0, 4)]),
+    op(0xA60, M8, "CP", &[Lit("YH"), Imm(0, 4)]),
+    op(0xA70, M8, "CP", &[Lit("YL"), Imm(0, 4)]),
+    // Data transfer
+    op(0xE00, M6, "LD", &[Reg(4), Imm(0, 4)]),
+    op(0xEC0, M8, "LD", &[Reg(2), Reg(0)]),
+    op(0xFA0, M8, "LD", &[Lit("A"), Mem]),
+    op(0xFB0, M8, "LD", &[Lit("B"), Mem]),
+    op(0xF80, M8, "LD", &[Mem, Lit("A")]),
+    op(0xF90, M8, "LD", &[Mem, Lit("B")]),
+    op(0xE60, M8, "LDPX", &[Lit("MX"), Imm(0, 4)]),
+    op(0xEE0, M8, "LDPX", &[Reg(2), Reg(0)]),
+    op(0xE70, M8, "LDPY", &[Lit("MY"), Imm(0, 4)]),
+    op(0xEF0, M8, "LDPY", &[Reg(2), Reg(0)]),
+    op(0x900, M4, "LBPX", &[Lit("MX"), Imm(0, 8)]),
+    // Flags
+    op(0xF41, M12, "SCF", &[]),
+    op(0xF42, M12, "SZF", &[]),
+    op(0xF44, M12, "SDF", &[]),
+    op(0xF48, M12, "EI", &[]),
+    op(0xF5E, M12, "RCF", &[]),
+    op(0xF5D, M12, "RZF", &[]),
+    op(0xF5B, M12, "RDF", &[]),
+    op(0xF57, M12, "DI", &[]),
+    op(0xF40, M8, "SET", &[Lit("F"), Imm(0, 4)]),
+    op(0xF50, M8, "RST", &[Lit("F"), Imm(0, 4)]),
+    // Stack
+    op(0xFDB, M12, "INC", &[Lit("SP")]),
+    op(0xFCB, M12, "DEC", &[Lit("SP")]),
+    op(0xFC0, M10, "PUSH", &[Reg(0)]),
+    op(0xFC4, M12, "PUSH", &[Lit("XP")]),
+    op(0xFC5, M12, "PUSH", &[Lit("XH")]),
+    op(0xFC6, M12, "PUSH", &[Lit("XL")]),
+    op(0xFC7, M12, "PUSH", &[Lit("YP")]),
+    op(0xFC8, M12, "PUSH", &[Lit("YH")]),
+    op(0xFC9, M12, "PUSH", &[Lit("YL")]),
+    op(0xFCA, M12, "PUSH", &[Lit("F")]),
+    op(0xFD0, M10, "POP", &[Reg(0)]),
+    op(0xFD4, M12, "POP", &[Lit("XP")]),
+    op(0xFD5, M12, "POP", &[Lit("XH")]),
+    op(0xFD6, M12, "POP", &[Lit("XL")]),
+    op(0xFD7, M12, "POP", &[Lit("YP")]),
+    op(0xFD8, M12, "POP", &[Lit("YH")]),
+    op(0xFD9, M12, "POP", &[Lit("YL")]),
+    op(0xFDA, M12, "POP", &[Lit("F")]),
+    op(0xFE0, M10, "LD", &[Lit("SPH"), Reg(0)]),
+    op(0xFF0, M10, "LD", &[Lit("SPL"), Reg(0)]),
+    op(0xFE4, M10, "LD", &[Reg(0), Lit("SPH")]),
+    op(0xFF4, M10, "LD", &[Reg(0), Lit("SPL")]),
+    // Arithmetic and logic
+    op(0xC00, M6, "ADD", &[Reg(4), Imm(0, 4)]),
+    op(0xA80, M8, "ADD", &[Reg(2), Reg(0)]),
+    op(0xC40, M6, "ADC", &[Reg(4), Imm(0, 4)]),
+    op(0xA90, M8, "ADC", &[Reg(2), Reg(0)]),
+    op(0xAA0, M8, "SUB", &[Reg(2), Reg(0)]),
+    op(0xD40, M6, "SBC", &[Reg(4), Imm(0, 4)]),
+    op(0xAB0, M8, "SBC", &[Reg(2), Reg(0)]),
+    op(0xC80, M6, "AND", &[Reg(4), Imm(0, 4)]),
+    op(0xAC0, M8, "AND", &[Reg(2), Reg(0)]),
+    op(0xCC0, M6, "OR", &[Reg(4), Imm(0, 4)]),
+    op(0xAD0, M8, "OR", &[Reg(2), Reg(0)]),
+    op(0xD0F, 0xFCF, "NOT", &[Reg(4)]),
+    op(0xD00, M6, "XOR", &[Reg(4), Imm(0, 4)]),
+    op(0xAE0, M8, "XOR", &[Reg(2), Reg(0)]),
+    op(0xDC0, M6, "CP", &[Reg(4), Imm(0, 4)]),
+    op(0xF00, M8, "CP", &[Reg(2), Reg(0)]),
+    op(0xD80, M6, "FAN", &[Reg(4), Imm(0, 4)]),
+    op(0xF10, M8, "FAN", &[Reg(2), Reg(0)]),
+    op(0xAF0, M8, "RLC", &[RegTwice]),
+    op(0xE8C, M10, "RRC", &[Reg(0)]),
+    op(0xF60, M8, "INC", &[Mem]),
+    op(0xF70, M8, "DEC", &[Mem]),
+    op(0xF28, M10, "ACPX", &[Lit("MX"), Reg(0)]),
+    op(0xF2C, M10, "ACPY", &[Lit("MY"), Reg(0)]),
+    op(0xF38, M10, "SCPX", &[Lit("MX"), Reg(0)]),
+    op(0xF3C, M10, "SCPY", &[Lit("MY"), Reg(0)]),
+];
+
+pub fn lookup(word: u16) -> Option<&'static OpSpec> {
+    OPS.iter().find(|spec| spec.matches(word & 0x0FFF))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    pub addr: u16,
+    pub word: u16,
+    pub spec: Option<&'static OpSpec>,
+}
+
+impl Instruction {
+    pub fn decode(addr: u16, word: u16) -> Self {
+        Self {
+            addr,
+            word: word & 0x0FFF,
+            spec: lookup(word),
+        }
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        self.spec.map_or(".word", |spec| spec.mnemonic)
+    }
+
+    pub fn operands(&self) -> Vec<String> {
+        let Some(spec) = self.spec else {
+            return vec![format!("0x{:03X}", self.word)];
+        };
+        spec.args
+            .iter()
+            .map(|arg| match *arg {
+                Lit(text) => text.to_string(),
+                Reg(shift) => REG_NAMES[((self.word >> shift) & 0x3) as usize].to_string(),
+                RegTwice => REG_NAMES[(self.word & 0x3) as usize].to_string(),
+                Imm(shift, bits) => format!("0x{:X}", (self.word >> shift) & ((1 << bits) - 1)),
+                Mem => format!("M{:X}", self.word & 0xF),
+                Step => format!("0x{:02X}", self.word & 0xFF),
+                Page => format!("0x{:02X}", self.word & 0x1F),
+            })
+            .collect()
+    }
+
+    /// Page selected by this instruction if it is a `PSET`.
+    pub fn pset_page(&self) -> Option<u16> {
+        self.spec
+            .filter(|spec| spec.args == [Page])
+            .map(|_| self.word & 0x1F)
+    }
+
+    /// Absolute target of a `JP`/`CALL`/`CALZ`, given the page `PSET` left
+    /// in `NP` (or `None` when the previous instruction was not a `PSET`).
+    pub fn branch_target(&self, pset: Option<u16>) -> Option<u16> {
+        let spec = self.spec?;
+        if !spec.args.contains(&Step) {
+            return None;
+        }
+        let step = self.word & 0xFF;
+        let page = if spec.mnemonic == "CALZ" {
+            (self.addr >> 8) & 0x10
+        } else {
+            pset.unwrap_or((self.addr >> 8) & 0x1F)
+        };
+        Some((page << 8) | step)
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let operands = self.operands();
+        if operands.is_empty() {
+            write!(f, "{}", self.mnemonic())
+        } else {
+            write!(f, "{:<4} {}", self.mnemonic(), operands.join(","))
+        }
+    }
+}
+
+pub fn disassemble(words: &[u16]) -> Vec<Instruction> {
+    words
+        .iter()
+        .enumerate()
+        .map(|(addr, &word)| Instruction::decode(addr as u16, word))
+        .collect()
+}
+
+/// Writes an annotated listing: labels for branch targets and vectors,
+/// then `address: word  instruction  ; comment` per line.
+pub fn write_listing<W: Write>(mut out: W, words: &[u16]) -> std::io::Result<()> {
+    let instructions = disassemble(words);
+
+    let mut targets = BTreeSet::new();
+    let mut resolved = Vec::with_capacity(instructions.len());
+    let mut pset = None;
+    for ins in &instructions {
+        let target = ins.branch_target(pset);
+        if let Some(target) = target {
+            targets.insert(target);
+        }
+        resolved.push(target);
+        pset = ins.pset_page();
+    }
+
+    for (ins, target) in instructions.iter().zip(resolved) {
+        if ins.addr == RESET_VECTOR {
+            writeln!(out, "\n; reset vector")?;
+        }
+        if let Some((_, name)) = INTERRUPT_VECTORS.iter().find(|(addr, _)| *addr == ins.addr) {
+            writeln!(out, "\n; {name} interrupt vector")?;
+        }
+        if targets.contains(&ins.addr) {
+            writeln!(out, "{}:", label(ins.addr))?;
+        }
+
+        let text = ins.to_string();
+        match target {
+            Some(target) => writeln!(
+                out,
+                "    {:04X}: {:03X}  {text:<20}; -> {}",
+                ins.addr,
+                ins.word,
+                label(target)
+            )?,
+            None => writeln!(out, "    {:04X}: {:03X}  {text}", ins.addr, ins.word)?,
+        }
+    }
+
+    out.flush()
+}
+
+pub fn label(addr: u16) -> String {
+    format!("L{addr:04X}")
+}
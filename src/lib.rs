@@ -1,4 +1,13 @@
+pub mod asm;
+pub mod audio;
+pub mod autosave;
+pub mod disasm;
+pub mod paths;
+pub mod pet;
 pub mod rom;
+pub mod romdb;
+pub mod save;
+pub mod slots;
 pub mod state;
 pub mod sys;
 pub mod tui;
@@ -6,11 +15,21 @@ pub mod tui;
 use std::path::Path;
 
 pub use rom::{decode_rom, load_rom_words, RomDecodeError, RomEncoding};
-pub use sys::{Button, CpuStateView, EngineError, TamaEngine};
+pub use sys::{
+    Button, ClockMode, CpuStateView, EngineConfig, EngineError, Frame, Icon, LogLevel, MemoryError,
+    Pacer, SoundEvent, SoundEventKind, StopReason, TamaEngine, TraceEntry, ICON_COUNT,
+};
 
 pub fn load_engine_from_file(path: &Path) -> Result<TamaEngine, EngineError> {
+    load_engine_with_config(path, EngineConfig::default())
+}
+
+pub fn load_engine_with_config(
+    path: &Path,
+    config: EngineConfig,
+) -> Result<TamaEngine, EngineError> {
     let rom = load_rom_words(path).map_err(EngineError::Rom)?;
-    TamaEngine::new(rom)
+    TamaEngine::with_config(rom, config)
 }
 
 #[cfg(test)]
@@ -25,6 +44,316 @@ mod tests {
         assert_eq!(restored[..words.len()], words);
     }
 
+    #[test]
+    fn detects_each_rom_encoding() {
+        let mut words = vec![0xFFBu16; rom::ROM_WORDS];
+        words[0x100] = 0xE41;
+        words[0x101] = 0x420;
+        for encoding in RomEncoding::ALL {
+            let bytes = rom::encode_rom(&words, encoding);
+            let ranked = rom::rank_encodings(&bytes);
+            assert_eq!(ranked[0].encoding, encoding);
+            assert_eq!(rom::detect_encoding(&bytes).ok(), Some(encoding));
+            assert_eq!(decode_rom(&bytes).expect("decode"), words);
+        }
+
+        let blank = vec![0u8; rom::ROM_WORDS * 2];
+        assert!(matches!(
+            rom::detect_encoding(&blank),
+            Err(RomDecodeError::Ambiguous { .. })
+        ));
+        assert_eq!(RomEncoding::parse("BE16"), Some(RomEncoding::Padded16Be12));
+        assert_eq!(RomEncoding::parse("le12"), None);
+    }
+
+    #[test]
+    fn square_wave_follows_buzzer_events() {
+        let events = [
+            SoundEvent {
+                tick: 0,
+                kind: SoundEventKind::Frequency(40960),
+            },
+            SoundEvent {
+                tick: 0,
+                kind: SoundEventKind::Start,
+            },
+            SoundEvent {
+                tick: 16384,
+                kind: SoundEventKind::Stop,
+            },
+        ];
+        let samples = audio::synthesize(&events, 0, 32768, 8000, 32768);
+        assert_eq!(samples.len(), 8000);
+        assert!(samples[..4000].iter().any(|&s| s > 0));
+        assert!(samples[..4000].iter().any(|&s| s < 0));
+        assert!(samples[4001..].iter().all(|&s| s == 0));
+
+        let mut wav = Vec::new();
+        audio::write_wav(&mut wav, &samples, 8000).expect("write wav");
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(wav.len(), 44 + samples.len() * 2);
+
+        // A late start renders only the tail, with the buzzer already on.
+        let tail = audio::synthesize(&events, 8192, 32768, 8000, 32768);
+        assert_eq!(tail.len(), 6000);
+        assert!(tail[..2000].iter().any(|&s| s != 0));
+
+        // The same ticks last twice as long on a half-speed oscillator.
+        let slow = audio::synthesize(&events, 0, 32768, 8000, 16384);
+        assert_eq!(slow.len(), 16000);
+        assert!(slow[..8000].iter().any(|&s| s != 0));
+        assert!(slow[8001..].iter().all(|&s| s == 0));
+        assert_eq!(events[2].time(16384), std::time::Duration::from_secs(1));
+
+        // Repeats are dropped and events before the start collapse into the
+        // initial state; streaming matches rendering up front.
+        let mut recorder = audio::WavRecorder::new(8000).starting_at(8192);
+        recorder.push_events(events);
+        recorder.push_events([
+            SoundEvent {
+                tick: 100,
+                kind: SoundEventKind::Frequency(20480),
+            },
+            events[2],
+        ]);
+        assert_eq!(recorder.events().len(), 3);
+        let mut streamed = Vec::new();
+        recorder
+            .write_wav(&mut streamed, 32768)
+            .expect("stream wav");
+        let mut rendered = Vec::new();
+        audio::write_wav(&mut rendered, &recorder.render(32768), 8000).expect("write wav");
+        assert_eq!(streamed, rendered);
+
+        let mut recorder = audio::WavRecorder::new(8000).starting_at(u64::MAX / 2);
+        recorder.push_events(events);
+        assert!(recorder.write_wav(std::io::sink(), u64::MAX).is_err());
+    }
+
+    #[test]
+    fn memory_nibbles_pack_across_regions() {
+        use sys::memory::{packed_index, read_nibble, write_nibble};
+
+        assert_eq!(packed_index(0x000), Some(0));
+        assert_eq!(packed_index(0x27F), Some(0x27F));
+        assert_eq!(packed_index(0x280), None);
+        assert_eq!(packed_index(0xE00), Some(0x280));
+        assert_eq!(packed_index(0xE80), Some(0x2D0));
+        assert_eq!(packed_index(0xF7F), Some(2 * state::MEM_BUFFER_SIZE - 1));
+        assert_eq!(packed_index(0xF80), None);
+
+        let mut buf = vec![0u8; state::MEM_BUFFER_SIZE];
+        write_nibble(&mut buf, 0x010, 0xA).expect("write even");
+        write_nibble(&mut buf, 0x011, 0x5).expect("write odd");
+        assert_eq!(buf[8], 0x5A);
+        assert_eq!(read_nibble(&buf, 0x011), Ok(0x5));
+        assert_eq!(
+            write_nibble(&mut buf, 0x010, 0x10),
+            Err(MemoryError::InvalidNibble {
+                addr: 0x010,
+                value: 0x10
+            })
+        );
+        assert_eq!(
+            read_nibble(&buf, 0x300),
+            Err(MemoryError::Unmapped { addr: 0x300 })
+        );
+    }
+
+    #[test]
+    fn disassembles_common_instructions() {
+        let cases = [
+            (0x012, "JP   0x12"),
+            (0x2FF, "JP   C,0xFF"),
+            (0xE41, "PSET 0x01"),
+            (0xE1A, "LD   B,0xA"),
+            (0xEC6, "LD   B,MX"),
+            (0xEE0, "INC  X"),
+            (0xEE6, "LDPX B,MX"),
+            (0xF41, "SCF"),
+            (0xF43, "SET  F,0x3"),
+            (0xD1F, "NOT  B"),
+            (0xD15, "XOR  B,0x5"),
+            (0xAF5, "RLC  B"),
+            (0xF8C, "LD   MC,A"),
+            (0xFDF, "RET"),
+            (0xFFA, ".word 0xFFA"),
+        ];
+        for (word, text) in cases {
+            assert_eq!(disasm::Instruction::decode(0, word).to_string(), text);
+        }
+
+        let pset = disasm::Instruction::decode(0x100, 0xE41);
+        let jump = disasm::Instruction::decode(0x101, 0x420);
+        assert_eq!(jump.branch_target(pset.pset_page()), Some(0x120));
+        assert_eq!(jump.branch_target(None), Some(0x120));
+        let far = disasm::Instruction::decode(0x1201, 0x033);
+        assert_eq!(far.branch_target(None), Some(0x1233));
+        assert_eq!(far.branch_target(Some(0x02)), Some(0x233));
+    }
+
+    #[test]
+    fn assembler_round_trips_disassembly() {
+        for word in 0..0x1000u16 {
+            let text = disasm::Instruction::decode(0x100, word).to_string();
+            let source = format!(".org 0x100\n{text}\n");
+            let words = asm::assemble(&source).expect(&text);
+            assert_eq!(
+                disasm::Instruction::decode(0x100, words[0x100]).to_string(),
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn assembler_resolves_labels_and_pages() {
+        let source = "
+            .equ value, 0x5
+            .org 0x100
+        start:
+            LD   A,value      ; comment
+            PSET far
+            CALL far
+        spin: JP spin
+            .org 0x234
+        far:
+            RET
+            .word 0x123, 7
+        ";
+        let words = asm::assemble(source).expect("assemble");
+        assert_eq!(words.len(), rom::ROM_WORDS);
+        assert_eq!(&words[0x100..0x104], &[0xE05, 0xE42, 0x434, 0x003]);
+        assert_eq!(&words[0x234..0x237], &[0xFDF, 0x123, 0x007]);
+
+        let err = asm::assemble(".org 0x100\nJP far\n.org 0x200\nfar: RET").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert!(asm::assemble("LD A,0x10").is_err());
+        assert!(asm::assemble("FOO A").is_err());
+    }
+
+    #[test]
+    fn assembled_program_runs() {
+        let words = asm::assemble(
+            "
+            .org 0x100
+            LD   A,0x9
+            LD   M3,A
+        done:
+            JP   done
+            ",
+        )
+        .expect("assemble");
+        let mut engine = TamaEngine::with_clock(words, ClockMode::Virtual).expect("engine init");
+        engine.tick_many(10);
+        assert_eq!(engine.read_mem(0x003), Ok(0x9));
+    }
+
+    #[test]
+    fn engines_keep_independent_state() {
+        let program = |value: u8| {
+            asm::assemble(&format!(
+                "
+                .org 0x100
+                LD   A,{value}
+                LD   M3,A
+            done:
+                JP   done
+                "
+            ))
+            .expect("assemble")
+        };
+        let mut first =
+            TamaEngine::with_clock(program(0x9), ClockMode::Virtual).expect("engine init");
+        let mut second =
+            TamaEngine::with_clock(program(0x5), ClockMode::Virtual).expect("engine init");
+
+        first.tick_many(10);
+        second.tick_many(10);
+        first.write_mem(0x010, 0x1).expect("write");
+        second.write_mem(0x010, 0x2).expect("write");
+        assert_eq!(first.read_mem(0x003), Ok(0x9));
+        assert_eq!(second.read_mem(0x003), Ok(0x5));
+        assert_eq!(first.read_mem(0x010), Ok(0x1));
+
+        let ticks = second.emulated_ticks();
+        drop(first);
+        assert_eq!(second.emulated_ticks(), ticks);
+        assert_eq!(second.read_mem(0x010), Ok(0x2));
+    }
+
+    #[test]
+    fn rom_identification_checks_structure_and_table() {
+        assert_eq!(romdb::rom_crc32(&[0x123, 0x456, 0xFFF]), 0xB136_11C5);
+
+        let blank = romdb::identify(&[0u16; 4096]);
+        assert!(!blank.supported);
+        assert!(blank.issues.contains(&romdb::RomIssue::Truncated {
+            words: 4096,
+            expected: rom::ROM_WORDS,
+        }));
+        assert!(blank.issues.contains(&romdb::RomIssue::Blank { word: 0 }));
+
+        let words = asm::assemble(".org 0x100\nNOP5\nloop: JP loop\n").expect("assemble");
+        let unknown = romdb::identify(&words);
+        assert!(unknown.supported);
+        assert_eq!(unknown.problems().count(), 0);
+
+        let table = [romdb::KnownRom {
+            crc32: romdb::rom_crc32(&words),
+            model: "Test",
+            variant: "loop",
+            words: rom::ROM_WORDS,
+            supported: false,
+        }];
+        let known = romdb::identify_with(&words, &table);
+        assert_eq!(known.model(), "Test");
+        assert!(!known.supported);
+
+        for (i, rom) in romdb::KNOWN_ROMS.iter().enumerate() {
+            assert_eq!(rom.words, rom::ROM_WORDS, "{}", rom.model);
+            assert!(romdb::KNOWN_ROMS[..i]
+                .iter()
+                .all(|other| other.crc32 != rom.crc32));
+        }
+    }
+
+    #[test]
+    fn pet_status_follows_layout() {
+        let layout = pet::RamLayout::parse(
+            "
+            # test map
+            hunger = 0x010
+            age    = 0x012:2 bcd
+            weight = 0x014:2
+            sick   = 0x016
+            ",
+        )
+        .expect("layout");
+
+        let mut mem = vec![0u8; state::MEM_BUFFER_SIZE];
+        let nibbles = [(0x010, 3), (0x012, 2), (0x013, 1), (0x014, 0xA), (0x015, 1)];
+        for (addr, value) in nibbles {
+            sys::memory::write_nibble(&mut mem, addr, value).expect("write");
+        }
+
+        let status = pet::PetStatus::decode(&mem, &layout);
+        assert_eq!(status.hunger_hearts, Some(3));
+        assert_eq!(status.age, Some(12));
+        assert_eq!(status.weight, Some(0x1A));
+        assert_eq!(status.sick, Some(false));
+        assert_eq!(status.clock, None);
+
+        assert!(pet::RamLayout::parse("mood = 0x010").is_err());
+        assert!(pet::RamLayout::parse("hunger = 0x300").is_err());
+
+        let wrapping = pet::Field {
+            addr: u16::MAX,
+            nibbles: 2,
+            ..pet::Field::nibble(0)
+        };
+        assert_eq!(wrapping.read(&mem), None);
+    }
+
     #[test]
     fn engine_can_tick_with_zero_rom() {
         let words = vec![0u16; 4096];
@@ -32,10 +361,356 @@ mod tests {
         engine.tick_many(16);
     }
 
+    #[test]
+    fn breakpoint_stops_execution() {
+        // Opcode 0x000 is `JP 0x00`, so a zero ROM spins at PC 0.
+        let mut engine = TamaEngine::new(vec![0u16; 4096]).expect("engine init");
+        assert!(engine.add_breakpoint(0));
+        assert!(!engine.add_breakpoint(0));
+
+        assert_eq!(
+            engine.tick_many(100),
+            Some(StopReason::Breakpoint { pc: 0 })
+        );
+        assert!(engine.is_paused());
+        assert_eq!(engine.tick(), None);
+
+        assert!(engine.remove_breakpoint(0));
+        engine.resume();
+        assert_eq!(engine.tick_many(100), None);
+        assert!(!engine.is_paused());
+    }
+
+    #[test]
+    fn step_into_executes_one_instruction() {
+        let mut engine = TamaEngine::new(vec![0u16; 4096]).expect("engine init");
+        let before = engine.state().expect("state").tick_counter;
+
+        let view = engine.step_into().expect("step");
+        assert_eq!(view.pc, 0);
+        assert!(view.tick_counter > before);
+        assert!(engine.is_paused());
+
+        engine.resume();
+        assert!(!engine.is_paused());
+    }
+
+    #[test]
+    fn trace_keeps_last_instructions() {
+        let mut engine = TamaEngine::new(vec![0u16; 4096]).expect("engine init");
+        engine.set_trace_capacity(4);
+        engine.tick_many(10);
+
+        let trace = engine.trace();
+        assert_eq!(trace.len(), 4);
+        assert!(trace
+            .windows(2)
+            .all(|w| w[0].state.tick_counter < w[1].state.tick_counter));
+
+        engine.set_trace_capacity(0);
+        engine.clear_trace();
+        engine.tick_many(10);
+        assert!(engine.trace().is_empty());
+    }
+
+    #[test]
+    fn poll_frame_returns_only_new_frames() {
+        let mut engine =
+            TamaEngine::with_clock(vec![0u16; 4096], ClockMode::Virtual).expect("engine init");
+        // Enough instructions to cover more than one refresh period.
+        engine.tick_many(1000);
+        let first = engine.poll_frame().expect("initial frame");
+        assert_eq!(first.number, 1);
+        assert_eq!(first.lcd, [[false; 32]; 16]);
+
+        // The zero ROM never draws, so no further frames are latched.
+        engine.tick_many(1000);
+        assert!(engine.poll_frame().is_none());
+    }
+
+    #[test]
+    fn virtual_clock_runs_are_reproducible() {
+        let run = || {
+            let mut engine =
+                TamaEngine::with_clock(vec![0u16; 4096], ClockMode::Virtual).expect("engine init");
+            engine.tick_many(5000);
+            let mut snapshot = engine.save_snapshot();
+            snapshot.saved_at = 0;
+            snapshot.to_bytes().expect("serialize")
+        };
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn oscillator_sets_emulated_time_rate() {
+        let config = EngineConfig {
+            clock: ClockMode::Virtual,
+            oscillator_hz: 1_000,
+        };
+        let mut engine = TamaEngine::with_config(vec![0u16; 4096], config).expect("engine init");
+        let ran = engine.fast_forward(std::time::Duration::from_secs(2), |_, _| {});
+        assert!(ran >= 2_000);
+        assert!(engine.emulated_time() >= std::time::Duration::from_secs(2));
+
+        let snapshot = engine.save_snapshot();
+        engine.tick_many(100);
+        let before = engine.emulated_ticks();
+        engine.load_snapshot(&snapshot);
+        assert_eq!(engine.emulated_ticks(), before);
+
+        let zero = EngineConfig {
+            oscillator_hz: 0,
+            ..EngineConfig::default()
+        };
+        assert!(matches!(
+            TamaEngine::with_config(vec![0u16; 4096], zero),
+            Err(EngineError::InvalidOscillator(0))
+        ));
+    }
+
+    #[test]
+    fn reset_cold_boots_but_keeps_the_clock() {
+        let mut engine =
+            TamaEngine::with_clock(vec![0u16; 4096], ClockMode::Virtual).expect("engine init");
+        engine.add_breakpoint(0x123);
+        engine.write_mem(0x010, 0x7).expect("write");
+        engine.tick_many(100);
+        let ticks = engine.emulated_ticks();
+
+        engine.reset().expect("reset");
+        assert_eq!(engine.read_mem(0x010), Ok(0));
+        assert_eq!(engine.emulated_ticks(), ticks);
+        assert_eq!(engine.breakpoints(), vec![0x123]);
+
+        // Short programs are padded to the whole PC range so tamalib never
+        // fetches past the end; the engine still reports what was loaded.
+        engine.reload_rom(vec![0u16; 16]).expect("reload");
+        assert_eq!(engine.rom_len_words(), 16);
+        assert_eq!(engine.rom(), &[0u16; 16]);
+        engine.tick_many(10);
+        assert!(engine.emulated_ticks() > ticks);
+    }
+
+    #[test]
+    fn snapshot_without_save_time_still_loads() {
+        let mut engine = TamaEngine::new(vec![0u16; 4096]).expect("engine init");
+        engine.tick_many(16);
+        let snapshot = engine.save_snapshot();
+        let bytes = snapshot.to_bytes().expect("serialize");
+
+        // Saves from before `saved_at` end right after `memory`.
+        let tail = bincode::serialized_size(&snapshot.peripherals).expect("size") as usize + 16;
+        let legacy = &bytes[..bytes.len() - tail];
+        let restored = state::Snapshot::from_bytes(legacy).expect("legacy load");
+        assert_eq!(restored.saved_at, 0);
+        assert!(restored.offline_duration().is_none());
+    }
+
+    #[test]
+    fn save_container_round_trips_and_rejects_damage() {
+        let engine = TamaEngine::new(vec![0u16; 4096]).expect("engine init");
+        let snapshot = engine.save_snapshot();
+        let payload = snapshot.to_bytes().expect("serialize");
+
+        let bytes = save::encode(&snapshot, 0x1234_5678).expect("encode");
+        let loaded = save::decode(&bytes).expect("decode");
+        assert_eq!(loaded.header.version, save::FORMAT_VERSION);
+        assert_eq!(loaded.header.rom_crc32, Some(0x1234_5678));
+        assert_eq!(loaded.snapshot.to_bytes().expect("serialize"), payload);
+        assert!(loaded.check_rom(0x1234_5678).is_ok());
+        assert!(matches!(
+            loaded.check_rom(0),
+            Err(save::SaveError::RomMismatch { .. })
+        ));
+
+        let mut flipped = bytes.clone();
+        flipped[40] ^= 1;
+        assert!(matches!(
+            save::decode(&flipped),
+            Err(save::SaveError::ChecksumMismatch { .. })
+        ));
+        assert!(matches!(
+            save::decode(&bytes[..bytes.len() - 1]),
+            Err(save::SaveError::Truncated { .. })
+        ));
+        let mut future = bytes.clone();
+        future[8] = 0xFF;
+        assert!(matches!(
+            save::decode(&future),
+            Err(save::SaveError::ChecksumMismatch { .. })
+        ));
+        let body = future.len() - 4;
+        let checksum = romdb::crc32(&future[..body]);
+        future[body..].copy_from_slice(&checksum.to_le_bytes());
+        assert!(matches!(
+            save::decode(&future),
+            Err(save::SaveError::UnsupportedVersion { .. })
+        ));
+        assert!(matches!(
+            save::decode(b"hello"),
+            Err(save::SaveError::NotASave)
+        ));
+
+        let peripherals = bincode::serialized_size(&snapshot.peripherals).expect("size") as usize;
+        let v1 = &payload[..payload.len() - peripherals - 8];
+        let legacy = save::decode(v1).expect("legacy decode");
+        assert_eq!(legacy.header.rom_crc32, None);
+        assert!(legacy.check_rom(0).is_ok());
+        let oldest = save::decode(&v1[..v1.len() - 8]).expect("v0 decode");
+        assert_eq!(oldest.header.version, 0);
+    }
+
+    #[test]
+    fn slots_rotate_backups_and_recover() {
+        let dir = std::env::temp_dir().join(format!("termatama-slots-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = slots::SlotStore::new(&dir).with_backups(2);
+        assert!(matches!(
+            store.path("../escape"),
+            Err(save::SaveError::InvalidSlot(_))
+        ));
+
+        let mut engine = TamaEngine::new(vec![0u16; 4096]).expect("engine init");
+        for _ in 0..4 {
+            engine.tick_many(100);
+            store.save("pet", &engine.save_snapshot(), 7).expect("save");
+        }
+        let age = engine.pet_age_ticks();
+        let path = store.path("pet").expect("path");
+        assert!(dir.join("pet.state.2").exists());
+        assert!(!dir.join("pet.state.3").exists());
+        assert!(!dir.join("pet.state.tmp").exists());
+
+        let listed = store.list().expect("list");
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].name, "pet");
+        assert_eq!(listed[0].backups, 2);
+        let meta = listed[0].meta.as_ref().expect("meta");
+        assert_eq!(meta.age_ticks, age);
+        assert!(meta.last_played > 0);
+
+        let mut restored = TamaEngine::new(vec![0u16; 4096]).expect("engine init");
+        restored.load_snapshot(&store.load("pet").expect("load").save.snapshot);
+        assert_eq!(restored.pet_age_ticks(), age);
+
+        std::fs::write(&path, b"torn").expect("damage");
+        let recovered = store.load("pet").expect("recover");
+        assert_eq!(recovered.path, dir.join("pet.state.1"));
+        assert!(recovered.damage.is_some());
+
+        // Lowering the count drops the generations above it on the next save.
+        let fewer = slots::SlotStore::new(&dir).with_backups(1);
+        fewer.save("pet", &engine.save_snapshot(), 7).expect("save");
+        assert!(dir.join("pet.state.1").exists());
+        assert!(!dir.join("pet.state.2").exists());
+        assert_eq!(fewer.list().expect("list")[0].backups, 1);
+
+        // Version 2 payloads predate the pet's age.
+        let current = save::encode(&engine.save_snapshot(), 7).expect("encode");
+        let payload_len = current.len() - 28 - 4 - 8;
+        let mut v2 = current[..28 + payload_len].to_vec();
+        v2[8..10].copy_from_slice(&2u16.to_le_bytes());
+        v2[24..28].copy_from_slice(&(payload_len as u32).to_le_bytes());
+        let checksum = romdb::crc32(&v2);
+        v2.extend_from_slice(&checksum.to_le_bytes());
+        let v2 = save::decode(&v2).expect("v2 decode");
+        assert_eq!(v2.header.version, 2);
+        assert_eq!(v2.snapshot.age_ticks, 0);
+
+        std::fs::remove_dir_all(&dir).expect("cleanup");
+    }
+
+    #[test]
+    fn autosave_counts_the_configured_clock() {
+        use std::time::Duration;
+
+        let mut engine =
+            TamaEngine::with_clock(vec![0u16; 4096], ClockMode::Virtual).expect("engine init");
+        let interval = Duration::from_secs(1);
+        let mut emulated =
+            autosave::Autosave::new(&engine, interval, autosave::AutosaveClock::Emulated);
+        let wall = autosave::Autosave::new(&engine, interval, autosave::AutosaveClock::Wall);
+        let off =
+            autosave::Autosave::new(&engine, Duration::ZERO, autosave::AutosaveClock::Emulated);
+
+        assert!(!emulated.due(&engine));
+        engine.fast_forward(Duration::from_millis(1500), |_, _| {});
+        assert!(emulated.due(&engine));
+        assert!(!wall.due(&engine));
+        assert!(!off.due(&engine));
+
+        emulated.saved(&engine);
+        assert!(!emulated.due(&engine));
+        assert_eq!(
+            autosave::AutosaveClock::parse("Emulated"),
+            Some(autosave::AutosaveClock::Emulated)
+        );
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    #[test]
+    fn dirs_follow_xdg_and_overrides() {
+        use std::ffi::OsString;
+        use std::path::PathBuf;
+
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(key, _)| *key == name)
+                    .map(|(_, value)| OsString::from(value))
+            }
+        };
+
+        let dirs = paths::Dirs::resolve(env(&[("HOME", "/home/pet")]));
+        assert_eq!(
+            dirs.saves,
+            PathBuf::from("/home/pet/.local/share/termatama/saves")
+        );
+        assert_eq!(dirs.config, PathBuf::from("/home/pet/.config/termatama"));
+        assert_eq!(
+            dirs.rom,
+            PathBuf::from("/home/pet/.local/share/termatama/roms/tama.b")
+        );
+
+        let dirs = paths::Dirs::resolve(env(&[
+            ("HOME", "/home/pet"),
+            ("XDG_DATA_HOME", "/data"),
+            ("XDG_CONFIG_HOME", "relative"),
+            ("TERMATAMA_ROM", "/roms/p1.b"),
+        ]));
+        assert_eq!(dirs.saves, PathBuf::from("/data/termatama/saves"));
+        assert_eq!(dirs.config, PathBuf::from("/home/pet/.config/termatama"));
+        assert_eq!(dirs.rom, PathBuf::from("/roms/p1.b"));
+
+        let dirs = paths::Dirs::resolve(env(&[
+            ("TERMATAMA_SAVE_DIR", "/srv/saves"),
+            ("TERMATAMA_CONFIG_DIR", ""),
+        ]));
+        assert_eq!(dirs.saves, PathBuf::from("/srv/saves"));
+        assert_eq!(dirs.config, PathBuf::from("."));
+    }
+
+    #[test]
+    fn snapshot_restores_buttons_and_rederives_the_screen() {
+        let mut engine = TamaEngine::new(vec![0u16; 4096]).expect("engine init");
+        engine.tick_many(64);
+        engine.set_button(Button::Middle, true);
+        let snapshot = engine.save_snapshot();
+        assert!(snapshot.peripherals.buttons[Button::Middle as usize]);
+
+        let mut restored = TamaEngine::new(vec![0u16; 4096]).expect("engine init");
+        restored.load_snapshot(&snapshot);
+        assert!(restored.save_snapshot().peripherals.buttons[Button::Middle as usize]);
+        assert_eq!(restored.get_lcd(), engine.get_lcd());
+        assert_eq!(restored.get_icons(), engine.get_icons());
+        assert_eq!(restored.buzzer(), engine.buzzer());
+        assert!(restored.poll_frame().is_some());
+    }
+
     #[test]
     fn optional_real_tama_b_smoke() {
         let root = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-        let path = root.join("roms\tama.b");
+        let path = root.join(paths::LEGACY_ROM);
         if !path.exists() {
             return;
         }
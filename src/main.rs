@@ -1,10 +1,19 @@
 use crossterm::event::{self, Event, KeyCode, KeyEventKind};
-use crossterm::terminal;
-use std::path::PathBuf;
-use std::time::{Duration, Instant};
-use termatama::state::Snapshot;
-use termatama::sys::Button;
+use crossterm::{cursor, terminal, ExecutableCommand};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use termatama::audio::WavRecorder;
+use termatama::autosave::{self, Autosave, AutosaveClock};
+use termatama::disasm;
+use termatama::paths::{self, Dirs};
+use termatama::save::{self, SaveError};
+use termatama::slots::{self, LoadedSlot, SlotStore};
+use termatama::sys::{Button, Pacer, MAX_SPEED, TICK_FREQUENCY};
 use termatama::tui::TuiRenderer;
+use termatama::{romdb, EngineConfig, EngineError, RomDecodeError, RomEncoding, TamaEngine};
 
 #[derive(Clone)]
 struct Keybind {
@@ -15,22 +24,46 @@ struct Keybind {
 
 struct Config {
     rom_path: PathBuf,
+    rom_format: Option<RomEncoding>,
     keybind: Keybind,
     speed: f64,
+    oscillator_hz: u32,
     headless: bool,
+    audio_out: Option<PathBuf>,
+    catch_up: bool,
+    catch_up_max: Option<Duration>,
+    slot: String,
+    backups: usize,
+    list_slots: bool,
+    save_dir: Option<PathBuf>,
+    autosave_interval: Duration,
+    autosave_clock: AutosaveClock,
 }
 
-fn parse_args() -> Config {
-    let mut rom_path = PathBuf::from("roms\\tama.b");
+/// Parses `args`: the options file's lines followed by the command line,
+/// so the command line wins.
+fn parse_args(args: Vec<String>, dirs: &Dirs) -> Config {
+    let mut rom_path = None;
+    let mut rom_format = None;
     let mut keybind = Keybind {
         left: 'z',
         middle: 'x',
         right: 'c',
     };
     let mut speed = 1.0_f64;
+    let mut oscillator_hz = TICK_FREQUENCY;
     let mut headless = false;
+    let mut audio_out = None;
+    let mut catch_up = true;
+    let mut catch_up_max = None;
+    let mut slot = slots::DEFAULT_SLOT.to_owned();
+    let mut backups = slots::DEFAULT_BACKUPS;
+    let mut list_slots = false;
+    let mut save_dir = None;
+    let mut autosave_interval = autosave::DEFAULT_INTERVAL;
+    let mut autosave_clock = AutosaveClock::Wall;
 
-    for arg in std::env::args().skip(1) {
+    for arg in args {
         if let Some(rest) = arg.strip_prefix("--keybind=") {
             for part in rest.split(|c| c == ',' || c == ' ') {
                 if let Some((k, v)) = part.split_once('=') {
@@ -50,34 +83,363 @@ fn parse_args() -> Config {
         }
 
         if let Some(rest) = arg.strip_prefix("--speed=") {
-            if let Ok(v) = rest.parse::<f64>() {
-                if v > 0.0 {
-                    speed = v;
+            match rest.parse::<f64>() {
+                Ok(v) if v > 0.0 && v <= MAX_SPEED => speed = v,
+                _ => {
+                    eprintln!(
+                        "invalid --speed value {rest:?}; expected a number in (0, {MAX_SPEED}]"
+                    );
+                    std::process::exit(2);
                 }
             }
             continue;
         }
 
+        if let Some(rest) = arg.strip_prefix("--rom-format=") {
+            rom_format = Some(parse_rom_format(rest));
+            continue;
+        }
+
+        if let Some(rest) = arg.strip_prefix("--oscillator=") {
+            match rest.parse::<u32>() {
+                Ok(v) if v > 0 => oscillator_hz = v,
+                _ => {
+                    eprintln!(
+                        "invalid --oscillator value {rest:?}; expected a frequency in Hz above 0"
+                    );
+                    std::process::exit(2);
+                }
+            }
+            continue;
+        }
+
+        if let Some(rest) = arg.strip_prefix("--audio-out=") {
+            if !rest.is_empty() {
+                audio_out = Some(PathBuf::from(rest));
+            }
+            continue;
+        }
+
+        if let Some(rest) = arg.strip_prefix("--catch-up-max=") {
+            catch_up_max = Some(parse_duration("--catch-up-max", rest, 3600.0));
+            continue;
+        }
+
+        if let Some(rest) = arg.strip_prefix("--slot=") {
+            slot = rest.to_owned();
+            continue;
+        }
+
+        if let Some(rest) = arg.strip_prefix("--backups=") {
+            if let Ok(v) = rest.parse::<usize>() {
+                backups = v;
+            }
+            continue;
+        }
+
+        if let Some(rest) = arg.strip_prefix("--autosave=") {
+            autosave_interval = parse_duration("--autosave", rest, 1.0);
+            continue;
+        }
+
+        if let Some(rest) = arg.strip_prefix("--autosave-clock=") {
+            match AutosaveClock::parse(rest) {
+                Some(clock) => autosave_clock = clock,
+                None => {
+                    eprintln!("unknown autosave clock {rest:?}: expected wall or emulated");
+                    std::process::exit(2);
+                }
+            }
+            continue;
+        }
+
+        if let Some(rest) = arg.strip_prefix("--save-dir=") {
+            if !rest.is_empty() {
+                save_dir = Some(PathBuf::from(rest));
+            }
+            continue;
+        }
+
+        if arg == "--list-slots" {
+            list_slots = true;
+            continue;
+        }
+
+        if arg == "--no-catch-up" {
+            catch_up = false;
+            continue;
+        }
+
         if arg == "--headless" {
             headless = true;
             continue;
         }
 
-        rom_path = PathBuf::from(arg);
+        rom_path = Some(PathBuf::from(arg));
     }
 
     Config {
-        rom_path,
+        rom_path: rom_path.unwrap_or_else(|| dirs.default_rom()),
+        rom_format,
         keybind,
         speed,
+        oscillator_hz,
         headless,
+        audio_out,
+        catch_up,
+        catch_up_max,
+        slot,
+        backups,
+        list_slots,
+        save_dir,
+        autosave_interval,
+        autosave_clock,
+    }
+}
+
+/// Fast-forwards through the offline time, stopping early once `shutdown`
+/// is set so the pet can be saved as far as it got.
+fn catch_up(
+    engine: &mut termatama::TamaEngine,
+    offline: Duration,
+    max: Option<Duration>,
+    shutdown: &AtomicBool,
+) {
+    /// Emulated time run between checks of `shutdown`.
+    const SLICE: Duration = Duration::from_secs(60);
+
+    let span = max.map_or(offline, |max| offline.min(max));
+    if span.is_zero() {
+        return;
+    }
+
+    eprintln!(
+        "Catching up {} of offline time{}",
+        format_duration(span),
+        if span < offline { " (capped)" } else { "" }
+    );
+    let total = engine.duration_to_ticks(span);
+    let slice = engine.duration_to_ticks(SLICE).max(1);
+    let mut done = 0;
+    let mut last_percent = u64::MAX;
+    while done < total {
+        if shutdown.load(Ordering::Relaxed) {
+            eprintln!("\r  interrupted");
+            return;
+        }
+        let ran = engine.run_ticks(slice.min(total - done), |_, _| {});
+        if ran == 0 {
+            break;
+        }
+        done += ran;
+        let percent = done * 100 / total;
+        if percent != last_percent {
+            last_percent = percent;
+            eprint!("\r  {percent:3}%");
+        }
+    }
+    eprintln!("\r  done");
+}
+
+fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    let (days, hours, mins) = (secs / 86_400, secs / 3600 % 24, secs / 60 % 60);
+    if days > 0 {
+        format!("{days}d {hours}h {mins}m")
+    } else if hours > 0 {
+        format!("{hours}h {mins}m")
+    } else {
+        format!("{mins}m {}s", secs % 60)
+    }
+}
+
+fn parse_rom_format(name: &str) -> RomEncoding {
+    RomEncoding::parse(name).unwrap_or_else(|| {
+        let names: Vec<&str> = RomEncoding::ALL.iter().map(|e| e.name()).collect();
+        eprintln!(
+            "unknown ROM format {name:?}; expected one of {}",
+            names.join(", ")
+        );
+        std::process::exit(2);
+    })
+}
+
+/// Parses a non-negative count of `unit_secs`-second units, exiting with
+/// a usage error if it is not a number or does not fit a `Duration`.
+fn parse_duration(flag: &str, value: &str, unit_secs: f64) -> Duration {
+    value
+        .parse::<f64>()
+        .ok()
+        .and_then(|units| Duration::try_from_secs_f64(units * unit_secs).ok())
+        .unwrap_or_else(|| {
+            eprintln!("invalid {flag} value {value:?}; expected a non-negative number");
+            std::process::exit(2);
+        })
+}
+
+/// `termatama disasm [--rom-format=F] <rom> [listing.txt]`: writes an
+/// annotated listing.
+fn run_disasm(args: &[String]) -> std::io::Result<()> {
+    let mut rom_format = None;
+    let mut positional = Vec::new();
+    for arg in args {
+        match arg.strip_prefix("--rom-format=") {
+            Some(name) => rom_format = Some(parse_rom_format(name)),
+            None => positional.push(arg),
+        }
+    }
+    let Some(rom_path) = positional.first() else {
+        eprintln!("usage: termatama disasm [--rom-format=F] <rom> [output]");
+        std::process::exit(2);
+    };
+
+    let words = match termatama::rom::load_rom_words_as(Path::new(rom_path), rom_format) {
+        Ok(words) => words,
+        Err(err) => {
+            eprintln!("failed to load {rom_path}: {err}");
+            std::process::exit(1);
+        }
+    };
+
+    match positional.get(1) {
+        Some(out_path) => {
+            let file = std::io::BufWriter::new(std::fs::File::create(out_path)?);
+            disasm::write_listing(file, &words)?;
+            println!("Wrote {} words to {out_path}", words.len());
+        }
+        None => disasm::write_listing(std::io::stdout().lock(), &words)?,
     }
+    Ok(())
+}
+
+/// Loads a ROM and adds a note to `notes` for anything that makes it
+/// unlikely to boot. The engine pads short dumps itself.
+fn load_checked_rom(
+    path: &Path,
+    format: Option<RomEncoding>,
+    notes: &mut Vec<String>,
+) -> Result<Vec<u16>, RomDecodeError> {
+    let words = termatama::rom::load_rom_words_as(path, format)?;
+    let info = romdb::identify(&words);
+    notes.push(match info.known {
+        Some(rom) => format!("ROM: {} ({})", rom.model, rom.variant),
+        None => format!(
+            "note: {} (CRC-32 {:08X}) is not a known dump",
+            path.display(),
+            info.crc32
+        ),
+    });
+    for issue in info.problems() {
+        notes.push(format!("warning: {}: {issue}", path.display()));
+    }
+    if !info.supported {
+        notes.push("warning: this ROM will probably not run correctly".to_owned());
+    }
+    Ok(words)
+}
+
+/// Where saves lived before slots: a single file in the working directory.
+/// Moved into the save directory's default slot on first run.
+const LEGACY_SAVE: &str = "termatama.state";
+
+/// `--list-slots`: one line per slot with the pet's age and last play time.
+fn list_slots(store: &SlotStore, oscillator_hz: u32) -> std::io::Result<()> {
+    let slots = match store.list() {
+        Ok(slots) => slots,
+        Err(err) => {
+            eprintln!("failed to list slots in {}: {err}", store.dir().display());
+            std::process::exit(1);
+        }
+    };
+    if slots.is_empty() {
+        println!("no save slots in {}", store.dir().display());
+    }
+    for slot in slots {
+        match slot.meta {
+            Ok(meta) => {
+                let age = meta.pet_age(oscillator_hz).as_secs();
+                let last_played = match meta.last_played {
+                    0 => "unknown".to_owned(),
+                    secs => format_unix_time(secs),
+                };
+                println!(
+                    "{:<16} age {}d {:02}h{:02}m  last played {last_played}  backups {}",
+                    slot.name,
+                    age / 86_400,
+                    age / 3_600 % 24,
+                    age / 60 % 60,
+                    slot.backups
+                );
+            }
+            Err(err) => println!("{:<16} unreadable: {err}", slot.name),
+        }
+    }
+    Ok(())
+}
+
+/// UTC `YYYY-MM-DD HH:MM` for seconds since the Unix epoch.
+fn format_unix_time(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    // Howard Hinnant's civil_from_days.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02} UTC",
+        secs / 3_600 % 24,
+        secs / 60 % 60
+    )
 }
 
 fn main() -> std::io::Result<()> {
-    let config = parse_args();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("disasm") {
+        return run_disasm(&args[1..]);
+    }
 
-    let mut engine = match termatama::load_engine_from_file(&config.rom_path) {
+    let dirs = Dirs::from_env();
+    let mut config_args = match paths::read_config_args(&dirs.config_file()) {
+        Ok(config_args) => config_args,
+        Err(err) => {
+            eprintln!("ignoring {}: {err}", dirs.config_file().display());
+            Vec::new()
+        }
+    };
+    config_args.extend(args);
+    let config = parse_args(config_args, &dirs);
+    let save_dir = config.save_dir.clone().unwrap_or(dirs.saves);
+    let store = SlotStore::new(save_dir).with_backups(config.backups);
+    if let Err(err) = store.path(&config.slot) {
+        eprintln!("{err}");
+        std::process::exit(2);
+    }
+    if config.list_slots {
+        return list_slots(&store, config.oscillator_hz);
+    }
+    match store.import_legacy(Path::new(LEGACY_SAVE), slots::DEFAULT_SLOT) {
+        Ok(true) => println!("Moved {LEGACY_SAVE} into slot {}", slots::DEFAULT_SLOT),
+        Ok(false) => {}
+        Err(err) => eprintln!("failed to import {LEGACY_SAVE}: {err}"),
+    }
+
+    let engine_config = EngineConfig {
+        oscillator_hz: config.oscillator_hz,
+        ..EngineConfig::default()
+    };
+    let mut notes = Vec::new();
+    let engine = load_checked_rom(&config.rom_path, config.rom_format, &mut notes)
+        .map_err(EngineError::Rom)
+        .and_then(|words| TamaEngine::with_config(words, engine_config));
+    for note in notes.drain(..) {
+        eprintln!("{note}");
+    }
+    let mut engine = match engine {
         Ok(engine) => engine,
         Err(err) => {
             eprintln!(
@@ -88,14 +450,46 @@ fn main() -> std::io::Result<()> {
         }
     };
 
-    let save_path = PathBuf::from("termatama.state");
-    if save_path.exists() {
-        if let Ok(bytes) = std::fs::read(&save_path) {
-            if let Ok(snapshot) = bincode::deserialize::<Snapshot>(&bytes) {
-                engine.load_snapshot(&snapshot);
-                println!("Loaded state from {}", save_path.display());
+    // Registered before the load so a signal during a long catch-up still
+    // ends in the final save.
+    let shutdown = Arc::new(AtomicBool::new(false));
+    register_shutdown_signals(&shutdown)?;
+    install_terminal_restoring_panic_hook();
+
+    let save_path = store.path(&config.slot).expect("slot name checked above");
+    match store.load(&config.slot) {
+        Ok(LoadedSlot { save, path, damage }) => {
+            if let Some(damage) = damage {
+                eprintln!("{} is unreadable ({damage})", save_path.display());
+                eprintln!("recovering from backup {}", path.display());
+            }
+            // Refuse rather than continue: exiting would overwrite the save.
+            if let Err(err) = save.check_rom(romdb::rom_crc32(engine.rom())) {
+                eprintln!("refusing to load {}: {err}", path.display());
+                eprintln!("load the matching ROM, or move the save aside to start over");
+                std::process::exit(1);
+            }
+            engine.load_snapshot(&save.snapshot);
+            println!("Loaded state from {}", path.display());
+            if save.header.version < save::FORMAT_VERSION {
+                println!(
+                    "Upgrading save from format {} to {} on exit",
+                    save.header.version,
+                    save::FORMAT_VERSION
+                );
+            }
+            if config.catch_up
+                && let Some(offline) = save.snapshot.offline_duration()
+            {
+                catch_up(&mut engine, offline, config.catch_up_max, &shutdown);
             }
         }
+        Err(SaveError::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => {
+            eprintln!("failed to load {}: {err}", save_path.display());
+            eprintln!("move the save aside to start over");
+            std::process::exit(1);
+        }
     }
 
     let mut renderer = if config.headless {
@@ -105,22 +499,112 @@ fn main() -> std::io::Result<()> {
         Some(TuiRenderer::new()?)
     };
 
-    let mut accumulator = Duration::ZERO;
-    let logic_step = Duration::from_secs_f64(1.0 / config.speed.max(0.01));
-    let mut last_time = Instant::now();
-    let logic_batch = ((1000.0 * config.speed).round() as usize).max(1);
-    let frame_batch = ((100.0 * config.speed).round() as usize).max(1);
+    // Starts after the offline catch-up, which is not worth listening to.
+    let mut recorder = config.audio_out.as_ref().map(|_| {
+        WavRecorder::default()
+            .with_oscillator(engine.oscillator_hz())
+            .starting_at(engine.emulated_ticks())
+    });
 
-    'main: loop {
-        let now = Instant::now();
-        let delta = now - last_time;
-        last_time = now;
-        accumulator += delta;
+    // However the loop ends (Esc or Ctrl+C, a termination signal, an I/O
+    // error such as a hung-up terminal, or a panic), the pet is saved below.
+    let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+        run(
+            &mut engine,
+            &config,
+            &store,
+            renderer.as_mut(),
+            recorder.as_mut(),
+            &shutdown,
+            &mut notes,
+        )
+    }));
+
+    // Restore the terminal first so the messages below stay visible.
+    drop(renderer);
+    if config.headless {
+        let _ = terminal::disable_raw_mode();
+    }
+    for note in notes {
+        eprintln!("{note}");
+    }
+
+    match save_state(&engine, &store, &config.slot) {
+        Ok(path) => println!("Saved state to {}", path.display()),
+        Err(err) => eprintln!("failed to write state to {}: {err}", save_path.display()),
+    }
+
+    if let (Some(rec), Some(path)) = (recorder.as_mut(), config.audio_out.as_ref()) {
+        rec.push_events(engine.drain_sound_events());
+        if let Err(err) = rec.save(path, engine.emulated_ticks()) {
+            eprintln!("failed to write audio to {}: {err}", path.display());
+        } else {
+            println!("Saved audio to {}", path.display());
+        }
+    }
+
+    match outcome {
+        Ok(result) => result,
+        Err(panic) => panic::resume_unwind(panic),
+    }
+}
+
+/// Writes the engine to its slot. The final save on exit and autosaves
+/// both come through here.
+fn save_state(engine: &TamaEngine, store: &SlotStore, slot: &str) -> Result<PathBuf, SaveError> {
+    store.save(
+        slot,
+        &engine.save_snapshot(),
+        romdb::rom_crc32(engine.rom()),
+    )
+}
+
+/// Turns termination signals into a request to leave the main loop, so
+/// they get the same final save as Esc. A second signal while that save
+/// is stuck exits at once.
+fn register_shutdown_signals(shutdown: &Arc<AtomicBool>) -> std::io::Result<()> {
+    let signals = signal_hook::consts::TERM_SIGNALS.iter().copied();
+    #[cfg(unix)]
+    let signals = signals.chain([signal_hook::consts::SIGHUP]);
+    for signal in signals {
+        // Order matters: the conditional exit only fires once the flag
+        // below has been set by an earlier signal.
+        signal_hook::flag::register_conditional_shutdown(signal, 1, Arc::clone(shutdown))?;
+        signal_hook::flag::register(signal, Arc::clone(shutdown))?;
+    }
+    Ok(())
+}
 
-        while accumulator >= logic_step {
-            engine.tick_many(logic_batch);
-            accumulator -= logic_step;
+/// Leaves the alternate screen before the panic message is printed;
+/// otherwise the message is drawn on the TUI screen and wiped with it.
+fn install_terminal_restoring_panic_hook() {
+    let default = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let mut stdout = std::io::stdout();
+        let _ = stdout.execute(terminal::LeaveAlternateScreen);
+        let _ = stdout.execute(cursor::Show);
+        let _ = terminal::disable_raw_mode();
+        default(info);
+    }));
+}
+
+fn run(
+    engine: &mut TamaEngine,
+    config: &Config,
+    store: &SlotStore,
+    mut renderer: Option<&mut TuiRenderer>,
+    mut recorder: Option<&mut WavRecorder>,
+    shutdown: &AtomicBool,
+    notes: &mut Vec<String>,
+) -> std::io::Result<()> {
+    let mut pacer = Pacer::new(engine, config.speed);
+    let mut autosave = Autosave::new(engine, config.autosave_interval, config.autosave_clock);
+
+    'main: loop {
+        if shutdown.load(Ordering::Relaxed) {
+            break 'main;
         }
+        pacer.advance(engine);
 
         if event::poll(Duration::from_millis(1))? {
             if let Event::Key(key) = event::read()? {
@@ -146,33 +630,50 @@ fn main() -> std::io::Result<()> {
                             engine.set_button(Button::Right, pressed);
                         }
                     }
+                    // Messages wait for exit: printing now would scribble
+                    // over the raw-mode screen.
+                    KeyCode::F(5) if key.kind == KeyEventKind::Press => {
+                        if let Err(err) = engine.reset() {
+                            notes.push(format!("reset failed: {err}"));
+                        }
+                    }
+                    KeyCode::F(6) if key.kind == KeyEventKind::Press => {
+                        match load_checked_rom(&config.rom_path, config.rom_format, notes) {
+                            Ok(words) => {
+                                if let Err(err) = engine.reload_rom(words) {
+                                    notes.push(format!("reload failed: {err}"));
+                                }
+                            }
+                            Err(err) => notes.push(format!(
+                                "failed to reload {}: {err}",
+                                config.rom_path.display()
+                            )),
+                        }
+                    }
                     KeyCode::Esc => break 'main,
                     _ => {}
                 }
             }
         }
 
-        engine.tick_many(frame_batch);
-
-        if let Some(r) = renderer.as_mut() {
-            let lcd = engine.get_lcd();
-            r.render(&lcd)?;
+        if let Some(rec) = recorder.as_deref_mut() {
+            rec.push_events(engine.drain_sound_events());
         }
 
-        std::thread::sleep(Duration::from_millis(10));
-    }
+        if let Some(frame) = engine.poll_frame()
+            && let Some(r) = renderer.as_deref_mut()
+        {
+            r.render(&frame.lcd, &frame.icons)?;
+        }
 
-    let snapshot = engine.save_snapshot();
-    if let Ok(bytes) = bincode::serialize(&snapshot) {
-        if let Err(err) = std::fs::write(&save_path, bytes) {
-            eprintln!("failed to write state to {}: {err}", save_path.display());
-        } else {
-            println!("Saved state to {}", save_path.display());
+        if autosave.due(engine) {
+            if let Err(err) = save_state(engine, store, &config.slot) {
+                notes.push(format!("autosave failed: {err}"));
+            }
+            autosave.saved(engine);
         }
-    }
 
-    if renderer.is_none() {
-        let _ = terminal::disable_raw_mode();
+        std::thread::sleep(Duration::from_millis(10));
     }
 
     Ok(())
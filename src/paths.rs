@@ -0,0 +1,115 @@
+//! Where termatama keeps its files.
+//!
+//! ```text
+//!          Linux and other Unix          macOS                                  Windows
+//! data     $XDG_DATA_HOME/termatama      ~/Library/Application Support/termatama  %APPDATA%\termatama
+//!          (~/.local/share/termatama)
+//! config   $XDG_CONFIG_HOME/termatama    same as data                           same as data
+//!          (~/.config/termatama)
+//! ```
+//!
+//! Saves go in `<data>/saves` and the default ROM is `<data>/roms/tama.b`.
+//! `TERMATAMA_SAVE_DIR`, `TERMATAMA_CONFIG_DIR` and `TERMATAMA_ROM` override
+//! each location. Without a home directory everything falls back to the
+//! working directory, as older builds did.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+pub const APP_DIR: &str = "termatama";
+pub const SAVE_DIR_ENV: &str = "TERMATAMA_SAVE_DIR";
+pub const CONFIG_DIR_ENV: &str = "TERMATAMA_CONFIG_DIR";
+pub const ROM_ENV: &str = "TERMATAMA_ROM";
+/// Options file in the config directory, one command-line argument per line.
+pub const CONFIG_FILE: &str = "termatama.conf";
+/// ROM location relative to the working directory used by older builds.
+pub const LEGACY_ROM: &str = "roms/tama.b";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dirs {
+    pub saves: PathBuf,
+    pub config: PathBuf,
+    /// ROM used when none is given on the command line.
+    pub rom: PathBuf,
+}
+
+impl Dirs {
+    pub fn from_env() -> Self {
+        Self::resolve(|name| std::env::var_os(name))
+    }
+
+    /// Resolves every location with `var` standing in for the environment.
+    pub fn resolve(var: impl Fn(&str) -> Option<OsString>) -> Self {
+        let path = |name: &str| {
+            var(name)
+                .filter(|value| !value.is_empty())
+                .map(PathBuf::from)
+        };
+        let (data, config) = match platform_dirs(&path) {
+            Some((data, config)) => (data.join(APP_DIR), config.join(APP_DIR)),
+            None => (PathBuf::from("."), PathBuf::from(".")),
+        };
+        Self {
+            saves: path(SAVE_DIR_ENV).unwrap_or_else(|| data.join("saves")),
+            config: path(CONFIG_DIR_ENV).unwrap_or(config),
+            rom: path(ROM_ENV).unwrap_or_else(|| data.join("roms").join("tama.b")),
+        }
+    }
+
+    pub fn config_file(&self) -> PathBuf {
+        self.config.join(CONFIG_FILE)
+    }
+
+    /// The ROM to run when none is given: [`Dirs::rom`], or the legacy
+    /// `roms/tama.b` in the working directory if only that one exists.
+    pub fn default_rom(&self) -> PathBuf {
+        let legacy = Path::new(LEGACY_ROM);
+        if !self.rom.exists() && legacy.exists() {
+            legacy.to_path_buf()
+        } else {
+            self.rom.clone()
+        }
+    }
+}
+
+/// Base data and config directories, before the app's own subdirectory.
+#[cfg(windows)]
+fn platform_dirs(path: &dyn Fn(&str) -> Option<PathBuf>) -> Option<(PathBuf, PathBuf)> {
+    let appdata = path("APPDATA").filter(|dir| dir.is_absolute())?;
+    Some((appdata.clone(), appdata))
+}
+
+#[cfg(target_os = "macos")]
+fn platform_dirs(path: &dyn Fn(&str) -> Option<PathBuf>) -> Option<(PathBuf, PathBuf)> {
+    let support = path("HOME")?.join("Library/Application Support");
+    Some((support.clone(), support))
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn platform_dirs(path: &dyn Fn(&str) -> Option<PathBuf>) -> Option<(PathBuf, PathBuf)> {
+    // The spec says relative XDG paths are invalid and must be ignored.
+    let xdg = |name: &str, fallback: &str| {
+        path(name)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| path("HOME").map(|home| home.join(fallback)))
+    };
+    Some((
+        xdg("XDG_DATA_HOME", ".local/share")?,
+        xdg("XDG_CONFIG_HOME", ".config")?,
+    ))
+}
+
+/// Reads the options file: one argument per line, blank lines and lines
+/// starting with `#` skipped. A missing file yields no arguments.
+pub fn read_config_args(path: &Path) -> std::io::Result<Vec<String>> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => Ok(text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_owned)
+            .collect()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err),
+    }
+}
@@ -0,0 +1,249 @@
+//! Game-level stats decoded from P1 RAM.
+//!
+//! Where the P1 ROM keeps each stat is described by a [`RamLayout`] rather
+//! than hardcoded, so a RAM map worked out with the debugger, trace and
+//! disassembler can be plugged in (or corrected) without rebuilding. Stats
+//! the layout does not cover decode as `None`.
+//!
+//! Blocked: there is no built-in P1 layout yet, so without a layout file
+//! every stat decodes as `None`. A `RamLayout::p1()` default for
+//! `TamaEngine::pet_status` needs addresses checked against RAM dumps of a
+//! running P1, which this tree cannot produce without the tamalib sources
+//! and a ROM.
+//!
+//! Layout files have one `name = ADDR[:NIBBLES] [bcd] [msb]` entry per
+//! line, with `#` comments. Multi-nibble values are read low nibble first
+//! unless `msb` is given; `bcd` decodes each nibble as a decimal digit.
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+use crate::state::Snapshot;
+use crate::sys::memory;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Binary,
+    Bcd,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field {
+    pub addr: u16,
+    pub nibbles: u8,
+    pub encoding: Encoding,
+    pub msb_first: bool,
+}
+
+impl Field {
+    pub fn nibble(addr: u16) -> Self {
+        Self {
+            addr,
+            nibbles: 1,
+            encoding: Encoding::Binary,
+            msb_first: false,
+        }
+    }
+
+    /// `None` if any nibble is unmapped, or not a digit in a BCD field.
+    pub fn read(&self, mem: &[u8]) -> Option<u32> {
+        let mut value = 0u32;
+        for i in 0..self.nibbles as u16 {
+            let pos = if self.msb_first {
+                i
+            } else {
+                self.nibbles as u16 - 1 - i
+            };
+            let nibble = memory::read_nibble(mem, self.addr.checked_add(pos)?).ok()? as u32;
+            value = match self.encoding {
+                Encoding::Binary => (value << 4) | nibble,
+                Encoding::Bcd if nibble > 9 => return None,
+                Encoding::Bcd => value * 10 + nibble,
+            };
+        }
+        Some(value)
+    }
+}
+
+/// Names accepted in layout files, in `PetStatus` field order.
+pub const FIELD_NAMES: [&str; 13] = [
+    "hunger",
+    "happiness",
+    "discipline",
+    "age",
+    "weight",
+    "character",
+    "stage",
+    "care_mistakes",
+    "sick",
+    "poop",
+    "asleep",
+    "clock_hour",
+    "clock_minute",
+];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RamLayout {
+    fields: HashMap<&'static str, Field>,
+}
+
+#[derive(Debug)]
+pub enum LayoutError {
+    Syntax { line: usize, message: String },
+    Io(std::io::Error),
+}
+
+impl Display for LayoutError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Syntax { line, message } => write!(f, "RAM layout line {line}: {message}"),
+            Self::Io(err) => write!(f, "RAM layout I/O error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for LayoutError {}
+
+impl From<std::io::Error> for LayoutError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl RamLayout {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets where `name` (one of [`FIELD_NAMES`]) lives; returns false for
+    /// unknown names.
+    pub fn set(&mut self, name: &str, field: Field) -> bool {
+        match FIELD_NAMES.iter().find(|known| **known == name) {
+            Some(known) => {
+                self.fields.insert(known, field);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Field> {
+        self.fields.get(name).copied()
+    }
+
+    pub fn load(path: &Path) -> Result<Self, LayoutError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, LayoutError> {
+        let mut layout = Self::new();
+        for (idx, raw) in text.lines().enumerate() {
+            let line = idx + 1;
+            let syntax = |message: String| LayoutError::Syntax { line, message };
+
+            let entry = raw.split('#').next().unwrap_or("").trim();
+            if entry.is_empty() {
+                continue;
+            }
+            let (name, spec) = entry
+                .split_once('=')
+                .ok_or_else(|| syntax("expected `name = ADDR[:NIBBLES]`".into()))?;
+            let name = name.trim();
+
+            let mut words = spec.split_whitespace();
+            let location = words
+                .next()
+                .ok_or_else(|| syntax(format!("missing address for {name}")))?;
+            let (addr, nibbles) = location.split_once(':').unwrap_or((location, "1"));
+            let addr = parse_number(addr).ok_or_else(|| syntax(format!("bad address {addr}")))?;
+            let nibbles = nibbles
+                .parse::<u8>()
+                .ok()
+                .filter(|n| (1..=8).contains(n))
+                .ok_or_else(|| syntax(format!("bad nibble count {nibbles}")))?;
+
+            let mut field = Field {
+                addr,
+                nibbles,
+                encoding: Encoding::Binary,
+                msb_first: false,
+            };
+            for flag in words {
+                match flag {
+                    "bcd" => field.encoding = Encoding::Bcd,
+                    "msb" => field.msb_first = true,
+                    other => return Err(syntax(format!("unknown flag {other}"))),
+                }
+            }
+            if memory::check_range(addr, nibbles as usize).is_err() {
+                return Err(syntax(format!("{name} is outside mapped memory")));
+            }
+            if !layout.set(name, field) {
+                return Err(syntax(format!("unknown field {name}")));
+            }
+        }
+        Ok(layout)
+    }
+}
+
+fn parse_number(text: &str) -> Option<u16> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockTime {
+    pub hour: u8,
+    pub minute: u8,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PetStatus {
+    pub hunger_hearts: Option<u8>,
+    pub happiness_hearts: Option<u8>,
+    pub discipline: Option<u8>,
+    pub age: Option<u32>,
+    pub weight: Option<u32>,
+    pub character: Option<u8>,
+    pub stage: Option<u8>,
+    pub care_mistakes: Option<u8>,
+    pub sick: Option<bool>,
+    pub poop_count: Option<u8>,
+    pub asleep: Option<bool>,
+    pub clock: Option<ClockTime>,
+}
+
+impl PetStatus {
+    /// Decodes stats from tamalib's packed memory buffer (live or snapshot).
+    pub fn decode(mem: &[u8], layout: &RamLayout) -> Self {
+        let read = |name: &str| layout.get(name).and_then(|field| field.read(mem));
+        let small = |name: &str| read(name).map(|v| v.min(u8::MAX as u32) as u8);
+
+        let clock = match (small("clock_hour"), small("clock_minute")) {
+            (Some(hour), Some(minute)) => Some(ClockTime { hour, minute }),
+            _ => None,
+        };
+
+        Self {
+            hunger_hearts: small("hunger"),
+            happiness_hearts: small("happiness"),
+            discipline: small("discipline"),
+            age: read("age"),
+            weight: read("weight"),
+            character: small("character"),
+            stage: small("stage"),
+            care_mistakes: small("care_mistakes"),
+            sick: read("sick").map(|v| v != 0),
+            poop_count: small("poop"),
+            asleep: read("asleep").map(|v| v != 0),
+            clock,
+        }
+    }
+
+    pub fn from_snapshot(snapshot: &Snapshot, layout: &RamLayout) -> Self {
+        Self::decode(&snapshot.memory, layout)
+    }
+}
@@ -2,6 +2,20 @@ use std::fmt::{Display, Formatter};
 use std::fs;
 use std::path::Path;
 
+use crate::disasm;
+
+/// Program ROM size of the E0C6S46 in 12-bit words.
+pub const ROM_WORDS: usize = 6144;
+
+/// Words the 13-bit program counter can address. Past `ROM_WORDS` there is
+/// no ROM, but a stray jump still makes tamalib fetch from there.
+pub const PC_SPACE_WORDS: usize = 0x2000;
+
+/// Confidence below which the best candidate is not trusted on its own.
+pub const MIN_CONFIDENCE: f32 = 0.5;
+/// Lead the best candidate needs over the runner-up to be trusted.
+pub const MIN_MARGIN: f32 = 0.1;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RomEncoding {
     Packed12Le,
@@ -9,9 +23,60 @@ pub enum RomEncoding {
     Padded16Be12,
 }
 
+impl RomEncoding {
+    pub const ALL: [RomEncoding; 3] = [
+        RomEncoding::Packed12Le,
+        RomEncoding::Padded16Le12,
+        RomEncoding::Padded16Be12,
+    ];
+
+    /// Name used by `--rom-format=`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Packed12Le => "packed12",
+            Self::Padded16Le12 => "le16",
+            Self::Padded16Be12 => "be16",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|encoding| encoding.name().eq_ignore_ascii_case(name))
+    }
+
+    fn fits(self, len: usize) -> bool {
+        len > 0
+            && match self {
+                Self::Packed12Le => len.is_multiple_of(3),
+                Self::Padded16Le12 | Self::Padded16Be12 => len.is_multiple_of(2),
+            }
+    }
+}
+
+impl Display for RomEncoding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// One way of reading a ROM file, with how plausible the result looks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Candidate {
+    pub encoding: RomEncoding,
+    /// 0.0 (garbage) to 1.0 (every check passed).
+    pub confidence: f32,
+}
+
 #[derive(Debug)]
 pub enum RomDecodeError {
-    InvalidLength { len: usize },
+    InvalidLength {
+        len: usize,
+    },
+    /// No encoding stands out; pass one explicitly.
+    Ambiguous {
+        candidates: Vec<Candidate>,
+    },
     Io(std::io::Error),
 }
 
@@ -19,6 +84,21 @@ impl Display for RomDecodeError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::InvalidLength { len } => write!(f, "invalid ROM byte length: {len}"),
+            Self::Ambiguous { candidates } => {
+                write!(f, "cannot tell the ROM encoding apart (")?;
+                for (i, candidate) in candidates.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(
+                        f,
+                        "{} {:.0}%",
+                        candidate.encoding,
+                        candidate.confidence * 100.0
+                    )?;
+                }
+                write!(f, "); choose one with --rom-format")
+            }
             Self::Io(err) => write!(f, "ROM I/O error: {err}"),
         }
     }
@@ -33,41 +113,126 @@ impl From<std::io::Error> for RomDecodeError {
 }
 
 pub fn load_rom_words(path: &Path) -> Result<Vec<u16>, RomDecodeError> {
+    load_rom_words_as(path, None)
+}
+
+/// Loads a ROM with a forced `encoding`, or detects it when `None`.
+pub fn load_rom_words_as(
+    path: &Path,
+    encoding: Option<RomEncoding>,
+) -> Result<Vec<u16>, RomDecodeError> {
     let bytes = fs::read(path)?;
-    decode_rom(&bytes)
+    match encoding {
+        Some(encoding) => decode_rom_as(&bytes, encoding),
+        None => decode_rom(&bytes),
+    }
 }
 
 pub fn decode_rom(bytes: &[u8]) -> Result<Vec<u16>, RomDecodeError> {
     let encoding = detect_encoding(bytes)?;
-    Ok(match encoding {
-        RomEncoding::Packed12Le => unpack_12bit_le(bytes)?,
-        RomEncoding::Padded16Le12 => unpack_16bit_container_le(bytes)?,
-        RomEncoding::Padded16Be12 => unpack_16bit_container_be(bytes)?,
-    })
+    decode_rom_as(bytes, encoding)
 }
 
-pub fn detect_encoding(bytes: &[u8]) -> Result<RomEncoding, RomDecodeError> {
-    if bytes.is_empty() {
-        return Err(RomDecodeError::InvalidLength { len: 0 });
+pub fn decode_rom_as(bytes: &[u8], encoding: RomEncoding) -> Result<Vec<u16>, RomDecodeError> {
+    match encoding {
+        RomEncoding::Packed12Le => unpack_12bit_le(bytes),
+        RomEncoding::Padded16Le12 => unpack_16bit_container_le(bytes),
+        RomEncoding::Padded16Be12 => unpack_16bit_container_be(bytes),
     }
+}
 
-    if bytes.len().is_multiple_of(2) {
-        let looks_padded_le = bytes.chunks_exact(2).all(|pair| (pair[0] & 0xF0) == 0);
-        if looks_padded_le {
-            return Ok(RomEncoding::Padded16Le12);
-        }
-
-        let looks_padded_be = bytes.chunks_exact(2).all(|pair| (pair[0] & 0xF0) == 0);
-        if looks_padded_be {
-            return Ok(RomEncoding::Padded16Be12);
-        }
+pub fn encode_rom(words: &[u16], encoding: RomEncoding) -> Vec<u8> {
+    match encoding {
+        RomEncoding::Packed12Le => pack_12bit_le(words),
+        RomEncoding::Padded16Le12 => pack_16bit_container_le(words),
+        RomEncoding::Padded16Be12 => pack_16bit_container_be(words),
     }
+}
 
-    if bytes.len().is_multiple_of(3) {
-        return Ok(RomEncoding::Packed12Le);
+/// Picks the best-scoring encoding, refusing to guess when the winner is
+/// weak or barely ahead of the runner-up.
+pub fn detect_encoding(bytes: &[u8]) -> Result<RomEncoding, RomDecodeError> {
+    let candidates = rank_encodings(bytes);
+    let Some(best) = candidates.first() else {
+        return Err(RomDecodeError::InvalidLength { len: bytes.len() });
+    };
+    let runner_up = candidates.get(1).map_or(0.0, |c| c.confidence);
+    if best.confidence < MIN_CONFIDENCE || best.confidence - runner_up < MIN_MARGIN {
+        return Err(RomDecodeError::Ambiguous { candidates });
     }
+    Ok(best.encoding)
+}
 
-    Err(RomDecodeError::InvalidLength { len: bytes.len() })
+/// Scores every encoding the byte length allows, best first.
+pub fn rank_encodings(bytes: &[u8]) -> Vec<Candidate> {
+    let mut candidates: Vec<Candidate> = RomEncoding::ALL
+        .into_iter()
+        .filter(|encoding| encoding.fits(bytes.len()))
+        .map(|encoding| Candidate {
+            encoding,
+            confidence: score(bytes, encoding),
+        })
+        .collect();
+    candidates.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+    candidates
+}
+
+/// Product of independent checks, each in 0.0..=1.0:
+/// - padding: 16-bit containers must leave the top nibble of every word
+///   clear, so real data in the other byte order fails almost everywhere;
+/// - length: a full E0C6S46 ROM is `ROM_WORDS` words;
+/// - vectors: the reset and interrupt vectors should hold instructions
+///   other than the fill word;
+/// - opcodes: the share of words that decode at all.
+fn score(bytes: &[u8], encoding: RomEncoding) -> f32 {
+    let padding = match encoding {
+        RomEncoding::Packed12Le => 1.0,
+        RomEncoding::Padded16Le12 => padding_clear(bytes, |pair| pair[1]),
+        RomEncoding::Padded16Be12 => padding_clear(bytes, |pair| pair[0]),
+    };
+    let Ok(words) = decode_rom_as(bytes, encoding) else {
+        return 0.0;
+    };
+
+    let length = match words.len() {
+        n if n == ROM_WORDS => 1.0,
+        n if n < ROM_WORDS => 0.6,
+        _ => 0.3,
+    };
+
+    let vectors: Vec<u16> = std::iter::once(disasm::RESET_VECTOR)
+        .chain(disasm::INTERRUPT_VECTORS.iter().map(|&(addr, _)| addr))
+        .filter_map(|addr| words.get(addr as usize).copied())
+        .collect();
+    let good_vectors = vectors
+        .iter()
+        .filter(|&&word| word != 0 && disasm::lookup(word).is_some())
+        .count();
+    let vectors = if vectors.is_empty() {
+        0.5
+    } else {
+        0.5 + 0.5 * good_vectors as f32 / vectors.len() as f32
+    };
+
+    let valid = words
+        .iter()
+        .filter(|&&word| disasm::lookup(word).is_some())
+        .count();
+    let opcodes = valid as f32 / words.len() as f32;
+
+    padding.powi(4) * length * vectors * opcodes
+}
+
+fn padding_clear(bytes: &[u8], high: impl Fn(&[u8]) -> u8) -> f32 {
+    let pairs = bytes.len() / 2;
+    if pairs == 0 {
+        return 0.0;
+    }
+    let clear = bytes
+        .chunks_exact(2)
+        .filter(|pair| high(pair) & 0xF0 == 0)
+        .count();
+    clear as f32 / pairs as f32
 }
 
 pub fn unpack_12bit_le(bytes: &[u8]) -> Result<Vec<u16>, RomDecodeError> {
@@ -110,9 +275,8 @@ pub fn unpack_16bit_container_le(bytes: &[u8]) -> Result<Vec<u16>, RomDecodeErro
 
     let mut out = Vec::with_capacity(bytes.len() / 2);
     for pair in bytes.chunks_exact(2) {
-        let hi_nibble = (pair[0] as u16 & 0x0F) << 8;
-        let lo_byte = pair[1] as u16;
-        out.push((hi_nibble | lo_byte) & 0x0FFF);
+        let word = u16::from_le_bytes([pair[0], pair[1]]);
+        out.push(word & 0x0FFF);
     }
     Ok(out)
 }
@@ -129,3 +293,17 @@ pub fn unpack_16bit_container_be(bytes: &[u8]) -> Result<Vec<u16>, RomDecodeErro
     }
     Ok(out)
 }
+
+pub fn pack_16bit_container_le(words: &[u16]) -> Vec<u8> {
+    words
+        .iter()
+        .flat_map(|word| (word & 0x0FFF).to_le_bytes())
+        .collect()
+}
+
+pub fn pack_16bit_container_be(words: &[u16]) -> Vec<u8> {
+    words
+        .iter()
+        .flat_map(|word| (word & 0x0FFF).to_be_bytes())
+        .collect()
+}
@@ -0,0 +1,176 @@
+use std::fmt::{Display, Formatter};
+
+use crate::disasm;
+use crate::rom::ROM_WORDS;
+
+/// A ROM dump whose checksum is known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KnownRom {
+    /// CRC-32 of the words as 16-bit big-endian values (see [`rom_crc32`]).
+    pub crc32: u32,
+    pub model: &'static str,
+    pub variant: &'static str,
+    pub words: usize,
+    /// Whether tamalib (an E0C6S46 core) can run it.
+    pub supported: bool,
+}
+
+/// Built-in table of dumps.
+///
+/// Each entry cites where its checksum comes from, and entries are only
+/// added with a public reference to check them against; a guessed checksum
+/// would vouch for a bad dump. ROMs missing here still get the structural
+/// checks in [`identify`], and callers with their own verified list can use
+/// [`identify_with`].
+pub const KNOWN_ROMS: &[KnownRom] = &[
+    // MAME's `tama` set: `tama.b`, 0x3000 bytes, CRC 5c864cb1.
+    KnownRom {
+        crc32: 0x5C86_4CB1,
+        model: "Tamagotchi P1",
+        variant: "tama.b",
+        words: ROM_WORDS,
+        supported: true,
+    },
+];
+
+/// Something about a ROM that makes it unlikely to boot correctly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RomIssue {
+    Truncated {
+        words: usize,
+        expected: usize,
+    },
+    Oversized {
+        words: usize,
+        expected: usize,
+    },
+    /// Every word has the same value, as in an erased or failed dump.
+    Blank {
+        word: u16,
+    },
+    /// The word at the reset vector is not an instruction.
+    BadResetVector {
+        word: u16,
+    },
+    /// The checksum is not in the table; it may still be a good dump.
+    UnknownDump,
+}
+
+impl Display for RomIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Truncated { words, expected } => {
+                write!(f, "truncated: {words} words, expected {expected}")
+            }
+            Self::Oversized { words, expected } => {
+                write!(f, "oversized: {words} words, expected {expected}")
+            }
+            Self::Blank { word } => write!(f, "blank: every word is 0x{word:03X}"),
+            Self::BadResetVector { word } => write!(
+                f,
+                "word 0x{word:03X} at reset vector 0x{:03X} is not an instruction",
+                disasm::RESET_VECTOR
+            ),
+            Self::UnknownDump => write!(f, "checksum not in the known-dump table"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomInfo {
+    pub crc32: u32,
+    pub words: usize,
+    pub known: Option<KnownRom>,
+    pub expected_words: usize,
+    /// Known and supported, or unknown but free of structural problems.
+    pub supported: bool,
+    pub issues: Vec<RomIssue>,
+}
+
+impl RomInfo {
+    pub fn model(&self) -> &'static str {
+        self.known.map_or("unknown", |rom| rom.model)
+    }
+
+    /// Issues worth warning about: everything except an unknown checksum
+    /// on an otherwise healthy dump.
+    pub fn problems(&self) -> impl Iterator<Item = &RomIssue> {
+        self.issues
+            .iter()
+            .filter(|issue| !matches!(issue, RomIssue::UnknownDump))
+    }
+}
+
+pub fn identify(words: &[u16]) -> RomInfo {
+    identify_with(words, KNOWN_ROMS)
+}
+
+pub fn identify_with(words: &[u16], table: &[KnownRom]) -> RomInfo {
+    let crc32 = rom_crc32(words);
+    let known = table.iter().find(|rom| rom.crc32 == crc32).copied();
+    let expected_words = known.map_or(ROM_WORDS, |rom| rom.words);
+
+    let mut issues = Vec::new();
+    if known.is_none() {
+        issues.push(RomIssue::UnknownDump);
+    }
+    if words.len() < expected_words {
+        issues.push(RomIssue::Truncated {
+            words: words.len(),
+            expected: expected_words,
+        });
+    } else if words.len() > expected_words {
+        issues.push(RomIssue::Oversized {
+            words: words.len(),
+            expected: expected_words,
+        });
+    }
+    if let Some(&first) = words.first()
+        && words.iter().all(|&word| word == first)
+    {
+        issues.push(RomIssue::Blank { word: first });
+    }
+    if let Some(&word) = words.get(disasm::RESET_VECTOR as usize)
+        && disasm::lookup(word).is_none()
+    {
+        issues.push(RomIssue::BadResetVector { word });
+    }
+
+    let supported = match known {
+        Some(rom) => rom.supported && issues.is_empty(),
+        None => issues.iter().all(|issue| *issue == RomIssue::UnknownDump),
+    };
+    RomInfo {
+        crc32,
+        words: words.len(),
+        known,
+        expected_words,
+        supported,
+        issues,
+    }
+}
+
+/// CRC-32 (IEEE) of `words` laid out as 16-bit big-endian values, which
+/// matches a checksum of a `.b` dump in that layout.
+pub fn rom_crc32(words: &[u16]) -> u32 {
+    !crc32_update(
+        !0,
+        words.iter().flat_map(|word| (word & 0x0FFF).to_be_bytes()),
+    )
+}
+
+/// CRC-32 (IEEE), as used by zip and PNG.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !crc32_update(!0, bytes.iter().copied())
+}
+
+fn crc32_update(mut crc: u32, bytes: impl Iterator<Item = u8>) -> u32 {
+    for byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    crc
+}
@@ -0,0 +1,350 @@
+//! Save file container.
+//!
+//! ```text
+//! 0   magic        b"TMTSAVE\0"
+//! 8   version      u16 LE
+//! 10  flags        u16 LE   bit 0: rom_crc32 is present
+//! 12  rom_crc32    u32 LE   see `romdb::rom_crc32`
+//! 16  created_at   u64 LE   seconds since the Unix epoch
+//! 24  payload_len  u32 LE
+//! 28  payload      bincode `Snapshot` in the layout of `version`
+//! ..  checksum     u32 LE   CRC-32 of every byte before it
+//! ```
+//!
+//! Versions 0 and 1 predate the container: the file was a bare payload,
+//! without and with `saved_at` respectively; the container starts at
+//! version 1. Version 2 added peripherals and version 3 the pet's age.
+//! Every older payload layout is frozen below and migrated forward on load;
+//! when `Snapshot` changes, freeze its current layout as a new struct and
+//! bump `FORMAT_VERSION`.
+
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::romdb::crc32;
+use crate::state::{Peripherals, Snapshot, SnapshotInterrupt};
+use crate::sys::ffi::{BoolT, U12T, U13T, U32T, U4T, U5T, U8T};
+
+pub const MAGIC: [u8; 8] = *b"TMTSAVE\0";
+/// Version written by this build.
+pub const FORMAT_VERSION: u16 = 3;
+
+const HEADER_LEN: usize = 28;
+const CHECKSUM_LEN: usize = 4;
+const FLAG_ROM_CRC: u16 = 1;
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(std::io::Error),
+    /// Neither a container nor a legacy bare payload.
+    NotASave,
+    UnsupportedVersion {
+        version: u16,
+    },
+    Truncated {
+        len: usize,
+        expected: usize,
+    },
+    ChecksumMismatch {
+        stored: u32,
+        computed: u32,
+    },
+    Corrupt(bincode::Error),
+    RomMismatch {
+        saved: u32,
+        loaded: u32,
+    },
+    /// Slot names are limited to ASCII letters, digits, `-` and `_`.
+    InvalidSlot(String),
+}
+
+impl Display for SaveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "save I/O error: {err}"),
+            Self::NotASave => write!(f, "not a termatama save file"),
+            Self::UnsupportedVersion { version } => write!(
+                f,
+                "save format version {version} is newer than this build supports ({FORMAT_VERSION})"
+            ),
+            Self::Truncated { len, expected } => {
+                write!(f, "save file truncated: {len} bytes, expected {expected}")
+            }
+            Self::ChecksumMismatch { stored, computed } => write!(
+                f,
+                "save checksum mismatch: stored {stored:08X}, computed {computed:08X}"
+            ),
+            Self::Corrupt(err) => write!(f, "save payload is corrupt: {err}"),
+            Self::RomMismatch { saved, loaded } => write!(
+                f,
+                "save was made with ROM {saved:08X}, but ROM {loaded:08X} is loaded"
+            ),
+            Self::InvalidSlot(name) => write!(
+                f,
+                "invalid slot name {name:?}: use letters, digits, '-' and '_'"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl SaveError {
+    /// Whether the file is missing or damaged, as opposed to intact but
+    /// unusable (a newer format, another ROM).
+    pub fn is_damage(&self) -> bool {
+        matches!(
+            self,
+            Self::Io(_)
+                | Self::NotASave
+                | Self::Truncated { .. }
+                | Self::ChecksumMismatch { .. }
+                | Self::Corrupt(_)
+        )
+    }
+}
+
+impl From<std::io::Error> for SaveError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SaveHeader {
+    pub version: u16,
+    /// `None` for legacy saves, which did not record the ROM.
+    pub rom_crc32: Option<u32>,
+    /// Seconds since the Unix epoch, 0 if unknown.
+    pub created_at: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct SaveFile {
+    pub header: SaveHeader,
+    pub snapshot: Snapshot,
+}
+
+impl SaveFile {
+    /// Fails if the save records a ROM other than `rom_crc32`. Legacy
+    /// saves carry no ROM hash and always pass.
+    pub fn check_rom(&self, rom_crc32: u32) -> Result<(), SaveError> {
+        match self.header.rom_crc32 {
+            Some(saved) if saved != rom_crc32 => Err(SaveError::RomMismatch {
+                saved,
+                loaded: rom_crc32,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+pub fn encode(snapshot: &Snapshot, rom_crc32: u32) -> Result<Vec<u8>, SaveError> {
+    let payload = snapshot.to_bytes().map_err(SaveError::Corrupt)?;
+    let created_at = match snapshot.saved_at {
+        0 => unix_now(),
+        saved_at => saved_at,
+    };
+
+    let mut out = Vec::with_capacity(HEADER_LEN + payload.len() + CHECKSUM_LEN);
+    out.extend_from_slice(&MAGIC);
+    out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    out.extend_from_slice(&FLAG_ROM_CRC.to_le_bytes());
+    out.extend_from_slice(&rom_crc32.to_le_bytes());
+    out.extend_from_slice(&created_at.to_le_bytes());
+    out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    out.extend_from_slice(&payload);
+    let checksum = crc32(&out);
+    out.extend_from_slice(&checksum.to_le_bytes());
+    Ok(out)
+}
+
+pub fn decode(bytes: &[u8]) -> Result<SaveFile, SaveError> {
+    if !bytes.starts_with(&MAGIC) {
+        let snapshot = decode_bare(bytes).map_err(|_| SaveError::NotASave)?;
+        let header = SaveHeader {
+            version: if snapshot.saved_at == 0 { 0 } else { 1 },
+            rom_crc32: None,
+            created_at: snapshot.saved_at,
+        };
+        return Ok(SaveFile { header, snapshot });
+    }
+
+    if bytes.len() < HEADER_LEN + CHECKSUM_LEN {
+        return Err(SaveError::Truncated {
+            len: bytes.len(),
+            expected: HEADER_LEN + CHECKSUM_LEN,
+        });
+    }
+    let payload_len = u32::from_le_bytes(bytes[24..28].try_into().unwrap()) as usize;
+    let expected = HEADER_LEN + payload_len + CHECKSUM_LEN;
+    if bytes.len() < expected {
+        return Err(SaveError::Truncated {
+            len: bytes.len(),
+            expected,
+        });
+    }
+    let body = &bytes[..HEADER_LEN + payload_len];
+    let stored = u32::from_le_bytes(bytes[body.len()..expected].try_into().unwrap());
+    let computed = crc32(body);
+    if stored != computed {
+        return Err(SaveError::ChecksumMismatch { stored, computed });
+    }
+
+    // Only trusted once the checksum matched: a flipped bit in the version
+    // is damage, not a save from a newer build.
+    let version = u16::from_le_bytes([bytes[8], bytes[9]]);
+    if version > FORMAT_VERSION {
+        return Err(SaveError::UnsupportedVersion { version });
+    }
+    let flags = u16::from_le_bytes([bytes[10], bytes[11]]);
+    let rom_crc32 = u32::from_le_bytes(bytes[12..16].try_into().unwrap());
+    let created_at = u64::from_le_bytes(bytes[16..24].try_into().unwrap());
+
+    let snapshot = decode_payload(version, &body[HEADER_LEN..])?;
+    let header = SaveHeader {
+        version,
+        rom_crc32: (flags & FLAG_ROM_CRC != 0).then_some(rom_crc32),
+        created_at,
+    };
+    Ok(SaveFile { header, snapshot })
+}
+
+pub fn read(path: &Path) -> Result<SaveFile, SaveError> {
+    decode(&fs::read(path)?)
+}
+
+/// Writes a save in place. Prefer [`crate::slots::SlotStore::save`],
+/// which cannot leave a half-written file behind.
+pub fn write(path: &Path, snapshot: &Snapshot, rom_crc32: u32) -> Result<(), SaveError> {
+    fs::write(path, encode(snapshot, rom_crc32)?)?;
+    Ok(())
+}
+
+/// Decodes a bare payload: the current layout, or one of the layouts older
+/// builds wrote straight to disk.
+pub(crate) fn decode_bare(bytes: &[u8]) -> Result<Snapshot, SaveError> {
+    decode_payload(FORMAT_VERSION, bytes)
+        .or_else(|_| decode_payload(1, bytes))
+        .or_else(|_| decode_payload(0, bytes))
+}
+
+fn decode_payload(version: u16, payload: &[u8]) -> Result<Snapshot, SaveError> {
+    match version {
+        0 => strict::<SnapshotV0>(payload).map(SnapshotV0::migrate),
+        1 => strict::<SnapshotV1>(payload).map(SnapshotV1::migrate),
+        2 => strict::<SnapshotV2>(payload).map(SnapshotV2::migrate),
+        3 => strict::<Snapshot>(payload),
+        version => Err(SaveError::UnsupportedVersion { version }),
+    }
+}
+
+/// Like `bincode::deserialize`, but refuses leftover bytes so a payload
+/// only decodes as the layout it was written in.
+fn strict<T: DeserializeOwned>(payload: &[u8]) -> Result<T, SaveError> {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .reject_trailing_bytes()
+        .deserialize(payload)
+        .map_err(SaveError::Corrupt)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Payload of version 0. Frozen: never edit, add a new version instead.
+#[derive(Deserialize)]
+struct SnapshotV0 {
+    pc: U13T,
+    x: U12T,
+    y: U12T,
+    a: U4T,
+    b: U4T,
+    np: U5T,
+    sp: U8T,
+    flags: U4T,
+    tick_counter: U32T,
+    clk_timer_2hz_timestamp: U32T,
+    clk_timer_4hz_timestamp: U32T,
+    clk_timer_8hz_timestamp: U32T,
+    clk_timer_16hz_timestamp: U32T,
+    clk_timer_32hz_timestamp: U32T,
+    clk_timer_64hz_timestamp: U32T,
+    clk_timer_128hz_timestamp: U32T,
+    clk_timer_256hz_timestamp: U32T,
+    prog_timer_timestamp: U32T,
+    prog_timer_enabled: BoolT,
+    prog_timer_data: U8T,
+    prog_timer_rld: U8T,
+    call_depth: U32T,
+    interrupts: Vec<SnapshotInterrupt>,
+    cpu_halted: BoolT,
+    memory: Vec<u8>,
+}
+
+impl SnapshotV0 {
+    fn migrate(self) -> Snapshot {
+        SnapshotV1(self, 0).migrate()
+    }
+}
+
+/// Payload of version 1: version 0 plus `saved_at`. Frozen.
+#[derive(Deserialize)]
+struct SnapshotV1(SnapshotV0, u64);
+
+impl SnapshotV1 {
+    fn migrate(self) -> Snapshot {
+        let SnapshotV1(v0, saved_at) = self;
+        SnapshotV2(v0, saved_at, Peripherals::default()).migrate()
+    }
+}
+
+/// Payload of version 2: version 1 plus `peripherals`. Frozen.
+#[derive(Deserialize)]
+struct SnapshotV2(SnapshotV0, u64, Peripherals);
+
+impl SnapshotV2 {
+    fn migrate(self) -> Snapshot {
+        let SnapshotV2(v0, saved_at, peripherals) = self;
+        Snapshot {
+            pc: v0.pc,
+            x: v0.x,
+            y: v0.y,
+            a: v0.a,
+            b: v0.b,
+            np: v0.np,
+            sp: v0.sp,
+            flags: v0.flags,
+            tick_counter: v0.tick_counter,
+            clk_timer_2hz_timestamp: v0.clk_timer_2hz_timestamp,
+            clk_timer_4hz_timestamp: v0.clk_timer_4hz_timestamp,
+            clk_timer_8hz_timestamp: v0.clk_timer_8hz_timestamp,
+            clk_timer_16hz_timestamp: v0.clk_timer_16hz_timestamp,
+            clk_timer_32hz_timestamp: v0.clk_timer_32hz_timestamp,
+            clk_timer_64hz_timestamp: v0.clk_timer_64hz_timestamp,
+            clk_timer_128hz_timestamp: v0.clk_timer_128hz_timestamp,
+            clk_timer_256hz_timestamp: v0.clk_timer_256hz_timestamp,
+            prog_timer_timestamp: v0.prog_timer_timestamp,
+            prog_timer_enabled: v0.prog_timer_enabled,
+            prog_timer_data: v0.prog_timer_data,
+            prog_timer_rld: v0.prog_timer_rld,
+            call_depth: v0.call_depth,
+            interrupts: v0.interrupts,
+            cpu_halted: v0.cpu_halted,
+            memory: v0.memory,
+            saved_at,
+            peripherals,
+            age_ticks: 0,
+        }
+    }
+}
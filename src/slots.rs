@@ -0,0 +1,244 @@
+//! Named save slots.
+//!
+//! A slot is `<name>.state` in the store's directory, with up to `backups`
+//! older generations beside it: `<name>.state.1` (newest) to
+//! `<name>.state.N`. A save is written to `<name>.state.tmp`, flushed, and
+//! renamed over the slot, so a crash mid-write leaves the previous save
+//! intact.
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::save::{self, SaveError, SaveFile, SaveHeader};
+use crate::state::Snapshot;
+
+pub const DEFAULT_SLOT: &str = "default";
+pub const DEFAULT_BACKUPS: usize = 3;
+
+const EXTENSION: &str = "state";
+
+pub struct SlotStore {
+    dir: PathBuf,
+    backups: usize,
+}
+
+/// A slot as found by [`SlotStore::load`].
+#[derive(Debug)]
+pub struct LoadedSlot {
+    pub save: SaveFile,
+    /// The file that was read: the slot itself or one of its backups.
+    pub path: PathBuf,
+    /// Why the slot itself was skipped, when a backup was loaded instead.
+    pub damage: Option<SaveError>,
+}
+
+/// A slot as listed by [`SlotStore::list`].
+#[derive(Debug)]
+pub struct SlotInfo {
+    pub name: String,
+    pub path: PathBuf,
+    pub meta: Result<SlotMeta, SaveError>,
+    /// Backup generations present on disk.
+    pub backups: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SlotMeta {
+    pub header: SaveHeader,
+    /// Emulated ticks the pet has lived, 0 for saves made before this was
+    /// recorded.
+    pub age_ticks: u64,
+    /// Seconds since the Unix epoch, 0 if unknown.
+    pub last_played: u64,
+}
+
+impl SlotMeta {
+    pub fn pet_age(&self, oscillator_hz: u32) -> Duration {
+        crate::sys::ticks_to_duration(self.age_ticks, oscillator_hz)
+    }
+}
+
+impl SlotStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            backups: DEFAULT_BACKUPS,
+        }
+    }
+
+    /// Sets how many older generations each save keeps; 0 disables backups.
+    pub fn with_backups(mut self, backups: usize) -> Self {
+        self.backups = backups;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn path(&self, slot: &str) -> Result<PathBuf, SaveError> {
+        validate(slot)?;
+        Ok(self.dir.join(format!("{slot}.{EXTENSION}")))
+    }
+
+    fn backup_path(&self, slot: &str, generation: usize) -> PathBuf {
+        self.dir.join(format!("{slot}.{EXTENSION}.{generation}"))
+    }
+
+    /// Reads a slot. When it is missing or damaged, falls back to the newest
+    /// backup that reads cleanly; if none does, returns the slot's own error.
+    /// Intact saves that are merely unusable (a newer format) are never
+    /// skipped, since the next save would bury them under the backup.
+    pub fn load(&self, slot: &str) -> Result<LoadedSlot, SaveError> {
+        let path = self.path(slot)?;
+        let damage = match save::read(&path) {
+            Ok(save) => {
+                return Ok(LoadedSlot {
+                    save,
+                    path,
+                    damage: None,
+                })
+            }
+            Err(err) if err.is_damage() => err,
+            Err(err) => return Err(err),
+        };
+
+        for generation in 1.. {
+            let backup = self.backup_path(slot, generation);
+            if !backup.exists() {
+                break;
+            }
+            if let Ok(save) = save::read(&backup) {
+                return Ok(LoadedSlot {
+                    save,
+                    path: backup,
+                    damage: Some(damage),
+                });
+            }
+        }
+        Err(damage)
+    }
+
+    /// Writes a slot atomically, first shifting the current save into the
+    /// backups, and deletes generations beyond the configured count (left
+    /// over from a higher `--backups`). Returns the slot's path.
+    pub fn save(
+        &self,
+        slot: &str,
+        snapshot: &Snapshot,
+        rom_crc32: u32,
+    ) -> Result<PathBuf, SaveError> {
+        let path = self.path(slot)?;
+        let bytes = save::encode(snapshot, rom_crc32)?;
+        fs::create_dir_all(&self.dir)?;
+
+        let tmp = self.dir.join(format!("{slot}.{EXTENSION}.tmp"));
+        let mut file = File::create(&tmp)?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+        drop(file);
+
+        if self.backups > 0 && path.exists() {
+            for generation in (1..self.backups).rev() {
+                let from = self.backup_path(slot, generation);
+                if from.exists() {
+                    fs::rename(&from, self.backup_path(slot, generation + 1))?;
+                }
+            }
+            // Copy rather than rename so the slot never goes missing.
+            fs::copy(&path, self.backup_path(slot, 1))?;
+        }
+        fs::rename(&tmp, &path)?;
+        for generation in self.backups + 1.. {
+            let stale = self.backup_path(slot, generation);
+            if !stale.exists() {
+                break;
+            }
+            fs::remove_file(stale)?;
+        }
+        sync_dir(&self.dir)?;
+        Ok(path)
+    }
+
+    /// Every slot in the store, sorted by name. Slots that fail to read are
+    /// listed with their error.
+    pub fn list(&self) -> Result<Vec<SlotInfo>, SaveError> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut slots = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(EXTENSION) {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            if validate(name).is_err() {
+                continue;
+            }
+            let meta = save::read(&path).map(|save| SlotMeta {
+                header: save.header,
+                age_ticks: save.snapshot.age_ticks,
+                last_played: save.header.created_at,
+            });
+            let backups = (1..)
+                .take_while(|&generation| self.backup_path(name, generation).exists())
+                .count();
+            slots.push(SlotInfo {
+                name: name.to_owned(),
+                path: path.clone(),
+                meta,
+                backups,
+            });
+        }
+        slots.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(slots)
+    }
+
+    /// Moves a save from before slots existed into `slot`, unless the slot
+    /// already exists. Returns whether anything was moved.
+    pub fn import_legacy(&self, legacy: &Path, slot: &str) -> Result<bool, SaveError> {
+        let path = self.path(slot)?;
+        if path.exists() || !legacy.exists() {
+            return Ok(false);
+        }
+        fs::create_dir_all(&self.dir)?;
+        if fs::rename(legacy, &path).is_err() {
+            // Across filesystems: copy, then remove the original.
+            fs::copy(legacy, &path)?;
+            fs::remove_file(legacy)?;
+        }
+        sync_dir(&self.dir)?;
+        Ok(true)
+    }
+}
+
+fn validate(slot: &str) -> Result<(), SaveError> {
+    let valid = !slot.is_empty()
+        && slot.len() <= 64
+        && slot
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+    if valid {
+        Ok(())
+    } else {
+        Err(SaveError::InvalidSlot(slot.to_owned()))
+    }
+}
+
+/// Makes the renames in `dir` durable. Only Unix can open a directory for
+/// this; elsewhere the rename itself is the best available.
+fn sync_dir(dir: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
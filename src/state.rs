@@ -1,5 +1,6 @@
 use super::sys::ffi::{BoolT, Interrupt, U12T, U13T, U32T, U4T, U5T, U8T};
 use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 pub const MEM_BUFFER_SIZE: usize = 464;
 pub const INT_SLOT_NUM: usize = 6;
@@ -31,6 +32,42 @@ pub struct Snapshot {
     pub interrupts: Vec<SnapshotInterrupt>,
     pub cpu_halted: BoolT,
     pub memory: Vec<u8>,
+    /// Wall-clock save time in seconds since the Unix epoch, 0 if unknown.
+    pub saved_at: u64,
+    pub peripherals: Peripherals,
+    /// Emulated ticks the pet has lived through across every session since
+    /// its cold boot, 0 if unknown.
+    pub age_ticks: u64,
+}
+
+/// Hardware state outside the CPU's memory. The screen, icons and buzzer
+/// are not kept here: tamalib re-derives them from the I/O registers in
+/// `memory` on load.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Peripherals {
+    /// Held buttons, indexed by `Button as usize`.
+    pub buttons: [bool; 4],
+}
+
+impl Snapshot {
+    pub fn to_bytes(&self) -> bincode::Result<Vec<u8>> {
+        bincode::serialize(self)
+    }
+
+    /// Decodes a bare payload from `to_bytes`, migrating older layouts.
+    /// Files on disk go through [`crate::save`], which adds a header.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, crate::save::SaveError> {
+        crate::save::decode_bare(bytes)
+    }
+
+    /// Wall-clock time elapsed since this snapshot was taken, if known.
+    pub fn offline_duration(&self) -> Option<Duration> {
+        if self.saved_at == 0 {
+            return None;
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+        now.checked_sub(Duration::from_secs(self.saved_at))
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
@@ -0,0 +1,92 @@
+use super::ffi::{Breakpoint, U13T};
+
+/// Upper bound on instructions executed by a single debugger command, so a
+/// `run_to_return` from the main loop cannot hang the caller.
+pub const DEBUG_STEP_LIMIT: usize = 1_000_000;
+
+/// Program counters are 13 bits wide, so this address can never match.
+const SENTINEL_ADDR: U13T = 0xFFFF;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    Breakpoint { pc: U13T },
+}
+
+/// Breakpoints in the linked-list layout tamalib walks after every step.
+///
+/// tamalib keeps the head pointer it was given at init, so the list starts
+/// with a fixed sentinel node and real breakpoints hang off its `next`. The
+/// nodes are relinked after every change since the `Vec` may reallocate.
+pub struct BreakpointList {
+    head: Box<Breakpoint>,
+    nodes: Vec<Breakpoint>,
+}
+
+impl BreakpointList {
+    pub fn new() -> Self {
+        Self {
+            head: Box::new(Breakpoint {
+                addr: SENTINEL_ADDR,
+                next: std::ptr::null_mut(),
+            }),
+            nodes: Vec::new(),
+        }
+    }
+
+    /// tamalib only ever reads the list, so handing out a mutable pointer
+    /// from a shared borrow is sound.
+    pub fn head_ptr(&self) -> *mut Breakpoint {
+        &*self.head as *const Breakpoint as *mut Breakpoint
+    }
+
+    pub fn contains(&self, addr: U13T) -> bool {
+        self.nodes.iter().any(|bp| bp.addr == addr)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn addrs(&self) -> Vec<U13T> {
+        self.nodes.iter().map(|bp| bp.addr).collect()
+    }
+
+    pub fn insert(&mut self, addr: U13T) -> bool {
+        if self.contains(addr) {
+            return false;
+        }
+        self.nodes.push(Breakpoint {
+            addr,
+            next: std::ptr::null_mut(),
+        });
+        self.relink();
+        true
+    }
+
+    pub fn remove(&mut self, addr: U13T) -> bool {
+        let before = self.nodes.len();
+        self.nodes.retain(|bp| bp.addr != addr);
+        self.relink();
+        self.nodes.len() != before
+    }
+
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.relink();
+    }
+
+    fn relink(&mut self) {
+        let mut next: *mut Breakpoint = std::ptr::null_mut();
+        for node in self.nodes.iter_mut().rev() {
+            node.next = next;
+            next = node;
+        }
+        self.head.next = next;
+    }
+}
+
+impl Default for BreakpointList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,5 +1,3 @@
-use std::ffi::c_void;
-
 pub type BoolT = u8;
 pub type U4T = u8;
 pub type U5T = u8;
@@ -17,6 +15,13 @@ pub struct Interrupt {
     pub vector: U8T,
 }
 
+#[repr(C)]
+#[derive(Debug)]
+pub struct Breakpoint {
+    pub addr: U13T,
+    pub next: *mut Breakpoint,
+}
+
 #[repr(C)]
 #[derive(Debug)]
 pub struct State {
@@ -56,7 +61,9 @@ pub struct CpuStateView {
     pub b: U4T,
     pub np: U5T,
     pub sp: U8T,
+    pub flags: U4T,
     pub tick_counter: U32T,
+    pub call_depth: U32T,
 }
 
 impl CpuStateView {
@@ -73,7 +80,9 @@ impl CpuStateView {
             b: unsafe { *state.b },
             np: unsafe { *state.np },
             sp: unsafe { *state.sp },
+            flags: unsafe { *state.flags },
             tick_counter: unsafe { *state.tick_counter },
+            call_depth: unsafe { *state.call_depth },
         })
     }
 }
@@ -106,11 +115,13 @@ pub enum ButtonState {
 }
 
 unsafe extern "C" {
-    pub fn tamalib_init(program: *const U12T, breakpoints: *mut c_void, freq: U32T) -> BoolT;
+    pub fn tamalib_init(program: *const U12T, breakpoints: *mut Breakpoint, freq: U32T) -> BoolT;
     pub fn tamalib_release();
     pub fn tamalib_set_exec_mode(mode: ExecMode);
     pub fn tamalib_step();
     pub fn cpu_get_state() -> *const State;
+    pub fn cpu_set_speed(speed: U8T);
     pub fn hw_set_button(btn: Button, state: ButtonState);
+    pub fn cpu_refresh_hw();
     pub fn tamars_register_hal();
 }
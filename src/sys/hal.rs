@@ -1,96 +1,424 @@
-use std::sync::{Mutex, OnceLock};
+use std::cell::{Cell, UnsafeCell};
+use std::collections::VecDeque;
 use std::time::Instant;
 
-use super::ffi::{BoolT, U32T, U8T};
+use super::ffi::{self, BoolT, U32T, U8T};
+use super::log::{LogLevel, LogSink};
+use super::sound::{SoundEvent, SoundEventKind, SOUND_QUEUE_CAPACITY};
+use super::TIMESTAMP_FREQUENCY;
+
+/// Source of the timestamps handed to tamalib through `rs_hal_get_timestamp`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ClockMode {
+    /// Host wall-clock microseconds; runs are paced by the caller.
+    #[default]
+    Wall,
+    /// Microseconds derived from executed CPU cycles, so identical inputs
+    /// always produce identical runs.
+    Virtual,
+}
+
+/// Screen refresh rate, matching tamalib's default `tamalib_set_framerate`.
+pub const FRAMERATE: u32 = 30;
+
+/// A completed LCD frame, latched when the screen is refreshed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    /// Increments each time a changed frame is latched.
+    pub number: u64,
+    /// Emulated ticks at the time the frame was latched.
+    pub tick: u64,
+    pub lcd: [[bool; 32]; 16],
+    pub icons: [bool; 8],
+    /// Bit `n` is set if row `n` changed since the previously polled frame.
+    pub dirty_rows: u16,
+    pub icons_changed: bool,
+}
 
 struct HalState {
+    clock: ClockMode,
+    oscillator_hz: u32,
     start: Instant,
     lcd: [[bool; 32]; 16],
     icons: [bool; 8],
+    latched_lcd: [[bool; 32]; 16],
+    latched_icons: [bool; 8],
+    frame_number: u64,
+    frame_tick: u64,
+    frame_pending: bool,
+    force_latch: bool,
+    dirty_rows: u16,
+    icons_dirty: bool,
+    last_refresh_tick: u64,
     frequency_dhz: u32,
     playing: bool,
+    sound_events: VecDeque<SoundEvent>,
+    last_tick_counter: u32,
+    emulated_ticks: u64,
+    log_mask: u8,
+    log_sink: Option<LogSink>,
+}
+
+impl HalState {
+    /// Extends tamalib's wrapping 32-bit tick counter into a monotonic count.
+    fn sync_emulated_ticks(&mut self) -> u64 {
+        let ptr = unsafe { ffi::cpu_get_state() };
+        if !ptr.is_null() {
+            let tick_counter = unsafe { *(*ptr).tick_counter };
+            self.emulated_ticks += tick_counter.wrapping_sub(self.last_tick_counter) as u64;
+            self.last_tick_counter = tick_counter;
+        }
+        self.emulated_ticks
+    }
+
+    fn timestamp(&mut self) -> U32T {
+        match self.clock {
+            ClockMode::Wall => {
+                let elapsed = self.start.elapsed().as_micros();
+                (elapsed.min(u32::MAX as u128)) as u32
+            }
+            ClockMode::Virtual => {
+                let ticks = self.sync_emulated_ticks() as u128;
+                // tamalib compares timestamps with wrapping arithmetic.
+                (ticks * TIMESTAMP_FREQUENCY as u128 / self.oscillator_hz as u128) as u32
+            }
+        }
+    }
+
+    /// What `update_screen` does: publishes the working LCD as a new frame
+    /// if anything changed since the last one.
+    fn latch_frame(&mut self) {
+        let mut dirty = 0u16;
+        for (row, (now, prev)) in self.lcd.iter().zip(&self.latched_lcd).enumerate() {
+            if now != prev {
+                dirty |= 1 << row;
+            }
+        }
+        let icons_changed = self.icons != self.latched_icons;
+        if dirty == 0 && !icons_changed && !self.force_latch {
+            return;
+        }
+
+        self.latched_lcd = self.lcd;
+        self.latched_icons = self.icons;
+        self.frame_number += 1;
+        self.frame_tick = self.emulated_ticks;
+        self.frame_pending = true;
+        self.force_latch = false;
+        self.dirty_rows |= dirty;
+        self.icons_dirty |= icons_changed;
+    }
+
+    /// Catches up on emulated time and refreshes the screen at `FRAMERATE`,
+    /// since `tamalib_step` (unlike `tamalib_mainloop`) never does.
+    fn sync(&mut self) -> u64 {
+        let ticks = self.sync_emulated_ticks();
+        if ticks - self.last_refresh_tick >= (self.oscillator_hz / FRAMERATE) as u64 {
+            self.last_refresh_tick = ticks;
+            self.latch_frame();
+        }
+        ticks
+    }
+
+    fn take_frame(&mut self) -> Option<Frame> {
+        if !self.frame_pending {
+            return None;
+        }
+        let frame = Frame {
+            number: self.frame_number,
+            tick: self.frame_tick,
+            lcd: self.latched_lcd,
+            icons: self.latched_icons,
+            dirty_rows: self.dirty_rows,
+            icons_changed: self.icons_dirty,
+        };
+        self.frame_pending = false;
+        self.dirty_rows = 0;
+        self.icons_dirty = false;
+        Some(frame)
+    }
+
+    fn push_sound_event(&mut self, kind: SoundEventKind) {
+        let tick = self.sync_emulated_ticks();
+        if self.sound_events.len() == SOUND_QUEUE_CAPACITY {
+            self.sound_events.pop_front();
+        }
+        self.sound_events.push_back(SoundEvent { tick, kind });
+    }
 }
 
 impl Default for HalState {
     fn default() -> Self {
         Self {
+            clock: ClockMode::Wall,
+            oscillator_hz: super::TICK_FREQUENCY,
             start: Instant::now(),
             lcd: [[false; 32]; 16],
             icons: [false; 8],
+            latched_lcd: [[false; 32]; 16],
+            latched_icons: [false; 8],
+            frame_number: 0,
+            frame_tick: 0,
+            frame_pending: false,
+            force_latch: true,
+            dirty_rows: 0,
+            icons_dirty: false,
+            last_refresh_tick: 0,
             frequency_dhz: 0,
             playing: false,
+            sound_events: VecDeque::new(),
+            last_tick_counter: 0,
+            emulated_ticks: 0,
+            log_mask: 0,
+            log_sink: None,
         }
     }
 }
 
-static HAL_STATE: OnceLock<Mutex<HalState>> = OnceLock::new();
+/// HAL state owned by one `TamaEngine`.
+///
+/// tamalib's callbacks carry no user pointer, so the engine enters its
+/// context (publishing a pointer in a thread-local) around every call into
+/// tamalib and the `rs_hal_*` callbacks work on whichever context is active.
+/// Everything runs on the engine's thread, so no locking is needed.
+pub struct HalContext {
+    state: Box<UnsafeCell<HalState>>,
+}
 
-fn state() -> &'static Mutex<HalState> {
-    HAL_STATE.get_or_init(|| Mutex::new(HalState::default()))
+thread_local! {
+    static ACTIVE: Cell<*mut HalState> = const { Cell::new(std::ptr::null_mut()) };
 }
 
-pub fn get_lcd_matrix() -> [[bool; 32]; 16] {
-    state().lock().expect("hal lock").lcd
+/// Restores the previously active context when dropped. It does not borrow
+/// the context so the engine stays usable; the engine keeps its context
+/// alive for longer than any guard it creates.
+pub struct Entered {
+    previous: *mut HalState,
 }
 
-pub fn install_hal() {
-    let _ = state();
-    unsafe {
-        super::ffi::tamars_register_hal();
+impl Drop for Entered {
+    fn drop(&mut self) {
+        ACTIVE.with(|active| active.set(self.previous));
     }
 }
 
-#[unsafe(no_mangle)]
-pub extern "C" fn rs_hal_halt() {}
+impl HalContext {
+    pub fn new(clock: ClockMode, oscillator_hz: u32) -> Self {
+        let state = HalState {
+            clock,
+            oscillator_hz,
+            ..HalState::default()
+        };
+        Self {
+            state: Box::new(UnsafeCell::new(state)),
+        }
+    }
 
-#[unsafe(no_mangle)]
-pub extern "C" fn rs_hal_is_log_enabled(_level: i32) -> BoolT {
-    0
+    /// Makes this context the target of HAL callbacks until the guard drops.
+    pub fn enter(&self) -> Entered {
+        let previous = ACTIVE.with(|active| active.replace(self.state.get()));
+        Entered { previous }
+    }
+
+    /// Drops HAL callbacks on the floor until the guard drops, for tamalib
+    /// calls whose side effects belong to no engine.
+    pub fn detached() -> Entered {
+        let previous = ACTIVE.with(|active| active.replace(std::ptr::null_mut()));
+        Entered { previous }
+    }
+
+    fn with<R>(&self, f: impl FnOnce(&mut HalState) -> R) -> R {
+        // Callbacks only run inside tamalib calls made by the owning engine,
+        // never while one of these accessors is executing.
+        f(unsafe { &mut *self.state.get() })
+    }
+
+    pub fn register(&self) {
+        let _active = self.enter();
+        unsafe {
+            ffi::tamars_register_hal();
+        }
+    }
+
+    pub fn clock(&self) -> ClockMode {
+        self.with(|s| s.clock)
+    }
+
+    pub fn oscillator_hz(&self) -> u32 {
+        self.with(|s| s.oscillator_hz)
+    }
+
+    pub fn lcd_matrix(&self) -> [[bool; 32]; 16] {
+        self.with(|s| s.lcd)
+    }
+
+    pub fn lcd_icons(&self) -> [bool; 8] {
+        self.with(|s| s.icons)
+    }
+
+    pub fn drain_sound_events(&self) -> Vec<SoundEvent> {
+        self.with(|s| s.sound_events.drain(..).collect())
+    }
+
+    pub fn buzzer_state(&self) -> (u32, bool) {
+        self.with(|s| (s.frequency_dhz, s.playing))
+    }
+
+    pub fn emulated_ticks(&self) -> u64 {
+        self.with(HalState::sync_emulated_ticks)
+    }
+
+    /// Syncs emulated time after a batch of steps, refreshing the screen if due.
+    pub fn sync(&self) -> u64 {
+        self.with(HalState::sync)
+    }
+
+    pub fn poll_frame(&self) -> Option<Frame> {
+        self.with(HalState::take_frame)
+    }
+
+    pub fn set_log_sink(&self, mask: u8, sink: Option<LogSink>) {
+        self.with(|s| {
+            s.log_mask = mask;
+            s.log_sink = sink;
+        });
+    }
+
+    pub fn set_log_mask(&self, mask: u8) {
+        self.with(|s| s.log_mask = mask);
+    }
+
+    /// Publishes what the HAL shows now as a frame, even if it is unchanged.
+    pub fn redraw(&self) {
+        self.with(|s| {
+            s.force_latch = true;
+            s.latch_frame();
+        });
+    }
+
+    /// Silences the buzzer and forces out the next frame, for a cold boot.
+    pub fn power_cycle(&self) {
+        self.with(|s| {
+            if s.playing {
+                s.playing = false;
+                s.push_sound_event(SoundEventKind::Stop);
+            }
+            s.force_latch = true;
+        });
+    }
+
+    /// Accepts tamalib's current tick counter as the new reference without
+    /// moving the emulated clock, after the counter was overwritten. Call
+    /// `sync` first so ticks run before the overwrite are counted.
+    pub fn rebase_ticks(&self) {
+        self.with(|s| {
+            let ptr = unsafe { ffi::cpu_get_state() };
+            if !ptr.is_null() {
+                s.last_tick_counter = unsafe { *(*ptr).tick_counter };
+            }
+        });
+    }
+
+    /// Re-bases the emulated clock after tamalib's tick counter was (re)initialized.
+    pub fn reset_clock(&self) {
+        self.with(|s| {
+            let ptr = unsafe { ffi::cpu_get_state() };
+            s.last_tick_counter = if ptr.is_null() {
+                0
+            } else {
+                unsafe { *(*ptr).tick_counter }
+            };
+            s.emulated_ticks = 0;
+            s.last_refresh_tick = 0;
+            s.force_latch = true;
+            s.sound_events.clear();
+        });
+    }
 }
 
+/// Runs `f` on the active context, or returns `default` outside any engine.
+#[inline]
+fn with_active<R>(default: R, f: impl FnOnce(&mut HalState) -> R) -> R {
+    let ptr = ACTIVE.with(Cell::get);
+    if ptr.is_null() {
+        return default;
+    }
+    f(unsafe { &mut *ptr })
+}
+
+pub(super) fn log_enabled(level: i32) -> bool {
+    with_active(false, |s| s.log_mask as i32 & level != 0)
+}
+
+pub(super) fn log_message(level: LogLevel, text: &str) {
+    with_active((), |s| {
+        if s.log_mask & level as u8 != 0
+            && let Some(sink) = s.log_sink.as_mut()
+        {
+            sink(level, text);
+        }
+    });
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn rs_hal_halt() {}
+
+/// Never blocks: in `Wall` mode the caller paces execution, and in `Virtual`
+/// mode time only advances by executing cycles, so any deadline tamalib asks
+/// for has already been reached once the instruction retired.
 #[unsafe(no_mangle)]
 pub extern "C" fn rs_hal_sleep_until(_ts: U32T) {}
 
 #[unsafe(no_mangle)]
 pub extern "C" fn rs_hal_get_timestamp() -> U32T {
-    let elapsed = state()
-        .lock()
-        .expect("hal lock")
-        .start
-        .elapsed()
-        .as_micros();
-    (elapsed.min(u32::MAX as u128)) as u32
+    with_active(0, HalState::timestamp)
 }
 
 #[unsafe(no_mangle)]
-pub extern "C" fn rs_hal_update_screen() {}
+pub extern "C" fn rs_hal_update_screen() {
+    with_active((), |s| {
+        s.sync_emulated_ticks();
+        s.latch_frame();
+    });
+}
 
 #[unsafe(no_mangle)]
 pub extern "C" fn rs_hal_set_lcd_matrix(x: U8T, y: U8T, val: BoolT) {
     if (x as usize) < 32 && (y as usize) < 16 {
-        let mut guard = state().lock().expect("hal lock");
-        guard.lcd[y as usize][x as usize] = val != 0;
+        with_active((), |s| s.lcd[y as usize][x as usize] = val != 0);
     }
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn rs_hal_set_lcd_icon(icon: U8T, val: BoolT) {
     if (icon as usize) < 8 {
-        let mut guard = state().lock().expect("hal lock");
-        guard.icons[icon as usize] = val != 0;
+        with_active((), |s| s.icons[icon as usize] = val != 0);
     }
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn rs_hal_set_frequency(freq: U32T) {
-    let mut guard = state().lock().expect("hal lock");
-    guard.frequency_dhz = freq;
+    with_active((), |s| {
+        if s.frequency_dhz != freq {
+            s.frequency_dhz = freq;
+            s.push_sound_event(SoundEventKind::Frequency(freq));
+        }
+    });
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn rs_hal_play_frequency(enabled: BoolT) {
-    let mut guard = state().lock().expect("hal lock");
-    guard.playing = enabled != 0;
+    let playing = enabled != 0;
+    with_active((), |s| {
+        if s.playing != playing {
+            s.playing = playing;
+            s.push_sound_event(if playing {
+                SoundEventKind::Start
+            } else {
+                SoundEventKind::Stop
+            });
+        }
+    });
 }
 
 #[unsafe(no_mangle)]
@@ -1,4 +1,5 @@
 #include <stdarg.h>
+#include <stdio.h>
 #include <stdlib.h>
 
 #include "../../vendor/tamalib/hal.h"
@@ -6,6 +7,7 @@
 
 extern void rs_hal_halt(void);
 extern bool_t rs_hal_is_log_enabled(int level);
+extern void rs_hal_log(int level, const char *msg);
 extern void rs_hal_sleep_until(timestamp_t ts);
 extern timestamp_t rs_hal_get_timestamp(void);
 extern void rs_hal_update_screen(void);
@@ -20,8 +22,18 @@ static bool_t bridge_is_log_enabled(log_level_t level) {
 }
 
 static void bridge_log(log_level_t level, char *buff, ...) {
-    (void)level;
-    (void)buff;
+    char msg[256];
+    va_list args;
+
+    if (!rs_hal_is_log_enabled((int)level)) {
+        return;
+    }
+
+    va_start(args, buff);
+    vsnprintf(msg, sizeof(msg), buff, args);
+    va_end(args);
+
+    rs_hal_log((int)level, msg);
 }
 
 static void *bridge_malloc(u32_t size) {
@@ -0,0 +1,314 @@
+use super::{ffi, hal, TamaEngine, TIMESTAMP_FREQUENCY};
+use crate::state::{Snapshot, SnapshotInterrupt, INT_SLOT_NUM, MEM_BUFFER_SIZE};
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+/// The parked CPU state of every engine that is not resident.
+///
+/// tamalib has exactly one CPU. Engines share it by swapping: before an
+/// engine touches tamalib, the resident engine's CPU is captured and parked
+/// here, tamalib is re-initialized with the incoming engine's ROM and
+/// breakpoints, and that engine's parked CPU is written back. Switching costs
+/// a snapshot and a re-init, so alternating batches of steps between engines
+/// is cheap while alternating single steps is not.
+struct Registry {
+    parked: BTreeMap<u64, Snapshot>,
+}
+
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+    parked: BTreeMap::new(),
+});
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Id of the engine inside tamalib right now, 0 when it is free. Taking
+/// this, rather than the registry lock, is all a call from the resident
+/// engine costs.
+static BUSY: AtomicU64 = AtomicU64::new(0);
+/// Id of the engine whose CPU is in tamalib's globals, 0 for none. Only
+/// written while holding `BUSY`.
+static RESIDENT: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// The engine whose `Active` guard this thread holds, so nested calls
+    /// from the same engine do not try to take the lock twice.
+    static HOLDER: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+pub fn next_id() -> u64 {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+fn lock() -> MutexGuard<'static, Registry> {
+    // A panic while swapping leaves the registry consistent enough to go on:
+    // at worst the resident engine loses its latest steps.
+    REGISTRY
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Waits until no other engine is inside tamalib and marks `id` as inside.
+/// Calls are short or batched, so waiting spins briefly before yielding.
+fn acquire(id: u64) {
+    let mut spins = 0u32;
+    while BUSY
+        .compare_exchange_weak(0, id, Ordering::Acquire, Ordering::Relaxed)
+        .is_err()
+    {
+        if spins < 64 {
+            spins += 1;
+            std::hint::spin_loop();
+        } else {
+            std::thread::yield_now();
+        }
+    }
+    HOLDER.with(|holder| holder.set(Some(id)));
+}
+
+/// Exclusive use of tamalib by one engine, with its HAL context entered.
+pub struct Active {
+    /// False for nested calls, which leave tamalib to the outer guard.
+    owner: bool,
+    _hal: hal::Entered,
+}
+
+impl Drop for Active {
+    fn drop(&mut self) {
+        if self.owner {
+            HOLDER.with(|holder| holder.set(None));
+            BUSY.store(0, Ordering::Release);
+        }
+    }
+}
+
+/// Takes tamalib for a brand-new engine, parking whichever engine is
+/// resident. The caller initializes tamalib and then calls [`claim`].
+pub fn begin_init(engine: &TamaEngine) -> Active {
+    assert_not_nested();
+    acquire(engine.id);
+    let active = Active {
+        owner: true,
+        _hal: engine.hal.enter(),
+    };
+    park_resident(&mut lock());
+    active
+}
+
+/// Records `engine` as resident after a successful `tamalib_init`.
+pub fn claim(active: &mut Active, engine: &TamaEngine) {
+    if active.owner {
+        RESIDENT.store(engine.id, Ordering::Relaxed);
+    }
+}
+
+/// Makes `engine` the resident of tamalib and enters its HAL context.
+/// When it already is, this is one atomic exchange and no lock.
+pub fn activate(engine: &TamaEngine) -> Active {
+    if HOLDER.with(Cell::get) == Some(engine.id) {
+        return Active {
+            owner: false,
+            _hal: engine.hal.enter(),
+        };
+    }
+    assert_not_nested();
+
+    acquire(engine.id);
+    // Built before swapping so a panic in the swap still frees tamalib.
+    let active = Active {
+        owner: true,
+        _hal: engine.hal.enter(),
+    };
+    if RESIDENT.load(Ordering::Relaxed) != engine.id {
+        let mut registry = lock();
+        park_resident(&mut registry);
+        swap_in(&mut registry, engine);
+    }
+    active
+}
+
+/// Releases tamalib if `engine` is resident; otherwise only forgets its
+/// parked CPU, leaving the resident engine untouched.
+pub fn release(engine: &TamaEngine) {
+    assert_not_nested();
+    acquire(engine.id);
+    let _active = Active {
+        owner: true,
+        _hal: engine.hal.enter(),
+    };
+    if RESIDENT.load(Ordering::Relaxed) == engine.id {
+        unsafe { ffi::tamalib_release() };
+        RESIDENT.store(0, Ordering::Relaxed);
+    } else {
+        lock().parked.remove(&engine.id);
+    }
+}
+
+fn assert_not_nested() {
+    // Only reachable from a HAL callback (e.g. a log sink) that drives a
+    // second engine while the first is inside tamalib; swapping the CPU out
+    // from under that call would corrupt it.
+    assert!(
+        HOLDER.with(Cell::get).is_none(),
+        "a TamaEngine cannot be used from inside another engine's callback"
+    );
+}
+
+fn park_resident(registry: &mut Registry) {
+    let id = RESIDENT.swap(0, Ordering::Relaxed);
+    if id != 0 {
+        registry.parked.insert(id, capture_cpu());
+        let _detached = hal::HalContext::detached();
+        unsafe { ffi::tamalib_release() };
+    }
+}
+
+fn swap_in(registry: &mut Registry, engine: &TamaEngine) {
+    {
+        // Re-init clears the LCD through the HAL; the engine's screen is
+        // already correct, so those callbacks are dropped.
+        let _detached = hal::HalContext::detached();
+        let result = unsafe {
+            ffi::tamalib_init(
+                engine.rom_words.as_ptr(),
+                engine.breakpoints.head_ptr(),
+                TIMESTAMP_FREQUENCY,
+            )
+        };
+        // The same ROM initialized successfully when the engine was created.
+        debug_assert_eq!(result, 0);
+        if let Some(snap) = registry.parked.remove(&engine.id) {
+            restore_cpu(&snap);
+            // Brings tamalib's hardware latches back in line with the
+            // restored I/O registers.
+            unsafe { ffi::cpu_refresh_hw() };
+        }
+    }
+
+    apply_settings(engine);
+    RESIDENT.store(engine.id, Ordering::Relaxed);
+}
+
+/// Re-initializes tamalib from scratch for `engine`, which must be resident
+/// with its `Active` guard held. Returns false if `tamalib_init` failed.
+pub fn cold_boot(engine: &TamaEngine) -> bool {
+    let ok = unsafe {
+        ffi::tamalib_release();
+        ffi::tamalib_init(
+            engine.rom_words.as_ptr(),
+            engine.breakpoints.head_ptr(),
+            TIMESTAMP_FREQUENCY,
+        ) == 0
+    };
+    if ok {
+        apply_settings(engine);
+    }
+    ok
+}
+
+/// Applies the engine-side settings a fresh `tamalib_init` knows nothing about.
+fn apply_settings(engine: &TamaEngine) {
+    unsafe {
+        if engine.hal.clock() == hal::ClockMode::Virtual {
+            ffi::cpu_set_speed(0);
+        }
+        for (index, &pressed) in engine.buttons.iter().enumerate() {
+            if pressed {
+                ffi::hw_set_button(super::BUTTONS[index], ffi::ButtonState::Pressed);
+            }
+        }
+        ffi::tamalib_set_exec_mode(if engine.paused {
+            ffi::ExecMode::Pause
+        } else {
+            ffi::ExecMode::Run
+        });
+    }
+}
+
+/// Copies the resident CPU out of tamalib. `saved_at`, `peripherals` and
+/// `age_ticks` are left at their defaults.
+pub fn capture_cpu() -> Snapshot {
+    unsafe {
+        let state_ptr = ffi::cpu_get_state();
+        let state = &*state_ptr;
+
+        let mut interrupts = Vec::with_capacity(INT_SLOT_NUM);
+        for i in 0..INT_SLOT_NUM {
+            let intr = *state.interrupts.add(i);
+            interrupts.push(SnapshotInterrupt::from(intr));
+        }
+
+        let mut memory = vec![0u8; MEM_BUFFER_SIZE];
+        std::ptr::copy_nonoverlapping(state.memory, memory.as_mut_ptr(), MEM_BUFFER_SIZE);
+
+        Snapshot {
+            pc: *state.pc,
+            x: *state.x,
+            y: *state.y,
+            a: *state.a,
+            b: *state.b,
+            np: *state.np,
+            sp: *state.sp,
+            flags: *state.flags,
+            tick_counter: *state.tick_counter,
+            clk_timer_2hz_timestamp: *state.clk_timer_2hz_timestamp,
+            clk_timer_4hz_timestamp: *state.clk_timer_4hz_timestamp,
+            clk_timer_8hz_timestamp: *state.clk_timer_8hz_timestamp,
+            clk_timer_16hz_timestamp: *state.clk_timer_16hz_timestamp,
+            clk_timer_32hz_timestamp: *state.clk_timer_32hz_timestamp,
+            clk_timer_64hz_timestamp: *state.clk_timer_64hz_timestamp,
+            clk_timer_128hz_timestamp: *state.clk_timer_128hz_timestamp,
+            clk_timer_256hz_timestamp: *state.clk_timer_256hz_timestamp,
+            prog_timer_timestamp: *state.prog_timer_timestamp,
+            prog_timer_enabled: *state.prog_timer_enabled,
+            prog_timer_data: *state.prog_timer_data,
+            prog_timer_rld: *state.prog_timer_rld,
+            call_depth: *state.call_depth,
+            interrupts,
+            cpu_halted: *state.cpu_halted,
+            memory,
+            saved_at: 0,
+            peripherals: Default::default(),
+            age_ticks: 0,
+        }
+    }
+}
+
+/// Writes `snap` over the resident CPU.
+pub fn restore_cpu(snap: &Snapshot) {
+    unsafe {
+        let state_ptr = ffi::cpu_get_state();
+        let state = &*state_ptr;
+
+        *state.pc = snap.pc;
+        *state.x = snap.x;
+        *state.y = snap.y;
+        *state.a = snap.a;
+        *state.b = snap.b;
+        *state.np = snap.np;
+        *state.sp = snap.sp;
+        *state.flags = snap.flags;
+        *state.tick_counter = snap.tick_counter;
+        *state.clk_timer_2hz_timestamp = snap.clk_timer_2hz_timestamp;
+        *state.clk_timer_4hz_timestamp = snap.clk_timer_4hz_timestamp;
+        *state.clk_timer_8hz_timestamp = snap.clk_timer_8hz_timestamp;
+        *state.clk_timer_16hz_timestamp = snap.clk_timer_16hz_timestamp;
+        *state.clk_timer_32hz_timestamp = snap.clk_timer_32hz_timestamp;
+        *state.clk_timer_64hz_timestamp = snap.clk_timer_64hz_timestamp;
+        *state.clk_timer_128hz_timestamp = snap.clk_timer_128hz_timestamp;
+        *state.clk_timer_256hz_timestamp = snap.clk_timer_256hz_timestamp;
+        *state.prog_timer_timestamp = snap.prog_timer_timestamp;
+        *state.prog_timer_enabled = snap.prog_timer_enabled;
+        *state.prog_timer_data = snap.prog_timer_data;
+        *state.prog_timer_rld = snap.prog_timer_rld;
+        *state.call_depth = snap.call_depth;
+        *state.cpu_halted = snap.cpu_halted;
+
+        for i in 0..INT_SLOT_NUM.min(snap.interrupts.len()) {
+            *state.interrupts.add(i) = ffi::Interrupt::from(snap.interrupts[i]);
+        }
+
+        let copy_len = MEM_BUFFER_SIZE.min(snap.memory.len());
+        std::ptr::copy_nonoverlapping(snap.memory.as_ptr(), state.memory, copy_len);
+    }
+}
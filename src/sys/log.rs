@@ -0,0 +1,50 @@
+use std::ffi::{c_char, CStr};
+
+use super::ffi::BoolT;
+use super::hal;
+
+/// tamalib's `log_level_t` bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogLevel {
+    Error = 0x1,
+    Info = 0x2,
+    Memory = 0x4,
+    Cpu = 0x8,
+}
+
+impl LogLevel {
+    pub const ALL: [LogLevel; 4] = [
+        LogLevel::Error,
+        LogLevel::Info,
+        LogLevel::Memory,
+        LogLevel::Cpu,
+    ];
+
+    fn from_bits(bits: i32) -> Option<Self> {
+        Self::ALL.into_iter().find(|level| *level as i32 == bits)
+    }
+
+    pub(super) fn mask(levels: &[LogLevel]) -> u8 {
+        levels.iter().fold(0, |mask, level| mask | *level as u8)
+    }
+}
+
+pub(super) type LogSink = Box<dyn FnMut(LogLevel, &str)>;
+
+#[unsafe(no_mangle)]
+pub extern "C" fn rs_hal_is_log_enabled(level: i32) -> BoolT {
+    hal::log_enabled(level) as BoolT
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn rs_hal_log(level: i32, msg: *const c_char) {
+    let Some(level) = LogLevel::from_bits(level) else {
+        return;
+    };
+    if msg.is_null() || !hal::log_enabled(level as i32) {
+        return;
+    }
+
+    let text = unsafe { CStr::from_ptr(msg) }.to_string_lossy();
+    hal::log_message(level, text.trim_end_matches(['\r', '\n']));
+}
@@ -0,0 +1,88 @@
+//! Nibble addressing for tamalib's LOW_FOOTPRINT memory buffer.
+//!
+//! The E0C6S46 sees a 4096-nibble data space, but only four regions of it
+//! are backed. tamalib packs those regions back to back, two nibbles per
+//! byte (even addresses in the low nibble), into `MEM_BUFFER_SIZE` bytes.
+
+use std::fmt::{Display, Formatter};
+
+pub const MEM_RAM_ADDR: u16 = 0x000;
+pub const MEM_RAM_SIZE: u16 = 0x280;
+pub const MEM_DISPLAY1_ADDR: u16 = 0xE00;
+pub const MEM_DISPLAY1_SIZE: u16 = 0x050;
+pub const MEM_DISPLAY2_ADDR: u16 = 0xE80;
+pub const MEM_DISPLAY2_SIZE: u16 = 0x050;
+pub const MEM_IO_ADDR: u16 = 0xF00;
+pub const MEM_IO_SIZE: u16 = 0x080;
+
+const REGIONS: [(u16, u16); 4] = [
+    (MEM_RAM_ADDR, MEM_RAM_SIZE),
+    (MEM_DISPLAY1_ADDR, MEM_DISPLAY1_SIZE),
+    (MEM_DISPLAY2_ADDR, MEM_DISPLAY2_SIZE),
+    (MEM_IO_ADDR, MEM_IO_SIZE),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryError {
+    Unmapped { addr: u16 },
+    InvalidNibble { addr: u16, value: u8 },
+}
+
+impl Display for MemoryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unmapped { addr } => write!(f, "memory address {addr:#05X} is not mapped"),
+            Self::InvalidNibble { addr, value } => {
+                write!(
+                    f,
+                    "value {value:#X} for {addr:#05X} does not fit in a nibble"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for MemoryError {}
+
+/// Position of `addr` in the packed buffer, counted in nibbles.
+pub fn packed_index(addr: u16) -> Option<usize> {
+    let mut base = 0usize;
+    for (start, size) in REGIONS {
+        if addr >= start && addr < start + size {
+            return Some(base + (addr - start) as usize);
+        }
+        base += size as usize;
+    }
+    None
+}
+
+pub fn read_nibble(buffer: &[u8], addr: u16) -> Result<u8, MemoryError> {
+    let idx = packed_index(addr).ok_or(MemoryError::Unmapped { addr })?;
+    let byte = buffer.get(idx >> 1).ok_or(MemoryError::Unmapped { addr })?;
+    Ok((byte >> ((idx & 1) << 2)) & 0x0F)
+}
+
+pub fn write_nibble(buffer: &mut [u8], addr: u16, value: u8) -> Result<(), MemoryError> {
+    if value > 0x0F {
+        return Err(MemoryError::InvalidNibble { addr, value });
+    }
+    let idx = packed_index(addr).ok_or(MemoryError::Unmapped { addr })?;
+    let byte = buffer
+        .get_mut(idx >> 1)
+        .ok_or(MemoryError::Unmapped { addr })?;
+    let shift = (idx & 1) << 2;
+    *byte = (*byte & !(0x0F << shift)) | (value << shift);
+    Ok(())
+}
+
+/// Checks every address of `addr..addr + len` before any access happens.
+pub fn check_range(addr: u16, len: usize) -> Result<(), MemoryError> {
+    for offset in 0..len {
+        let a = addr as usize + offset;
+        let a16 = u16::try_from(a).unwrap_or(u16::MAX);
+        if packed_index(a16).is_none() {
+            return Err(MemoryError::Unmapped { addr: a16 });
+        }
+    }
+    Ok(())
+}
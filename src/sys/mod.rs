@@ -1,14 +1,57 @@
+mod debug;
 pub(crate) mod ffi;
 mod hal;
+mod instance;
+mod log;
+pub mod memory;
+mod pacing;
+mod sound;
+mod trace;
 
-use crate::state::{Snapshot, SnapshotInterrupt, INT_SLOT_NUM, MEM_BUFFER_SIZE};
+use crate::state::{Peripherals, Snapshot, MEM_BUFFER_SIZE};
 use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+pub use debug::StopReason;
 pub use ffi::{Button, CpuStateView};
+pub use hal::{ClockMode, Frame, FRAMERATE};
+pub use log::LogLevel;
+pub use memory::MemoryError;
+pub use pacing::{Pacer, MAX_SPEED};
+pub use sound::{ticks_to_duration, SoundEvent, SoundEventKind};
+pub use trace::TraceEntry;
+
+/// Rate of tamalib's `tick_counter`, i.e. the E0C6S46 OSC1 crystal.
+pub const TICK_FREQUENCY: u32 = 32_768;
+/// Resolution of the timestamps exchanged with tamalib (microseconds). This
+/// is a unit, not a clock speed; emulated time comes from `oscillator_hz`.
+pub const TIMESTAMP_FREQUENCY: u32 = 1_000_000;
+
+/// How an engine maps its executed cycles onto time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EngineConfig {
+    pub clock: ClockMode,
+    /// Ticks per emulated second. Defaults to the real 32.768 kHz crystal;
+    /// other values trim the pet's clock the way a fast or slow crystal would.
+    pub oscillator_hz: u32,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            clock: ClockMode::Wall,
+            oscillator_hz: TICK_FREQUENCY,
+        }
+    }
+}
 
 #[derive(Debug)]
 pub enum EngineError {
     InitFailed,
+    InvalidOscillator(u32),
     Rom(crate::rom::RomDecodeError),
 }
 
@@ -16,6 +59,7 @@ impl Display for EngineError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::InitFailed => write!(f, "tamalib_init returned failure"),
+            Self::InvalidOscillator(hz) => write!(f, "invalid oscillator frequency {hz} Hz"),
             Self::Rom(err) => write!(f, "{err}"),
         }
     }
@@ -23,127 +67,502 @@ impl Display for EngineError {
 
 impl std::error::Error for EngineError {}
 
+/// Indexed by `Button as usize`.
+const BUTTONS: [Button; 4] = [Button::Left, Button::Middle, Button::Right, Button::Tap];
+
+pub const ICON_COUNT: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Icon {
+    Food = 0,
+    Light = 1,
+    Game = 2,
+    Medicine = 3,
+    Bathroom = 4,
+    Status = 5,
+    Training = 6,
+    Attention = 7,
+}
+
+impl Icon {
+    pub const ALL: [Icon; ICON_COUNT] = [
+        Icon::Food,
+        Icon::Light,
+        Icon::Game,
+        Icon::Medicine,
+        Icon::Bathroom,
+        Icon::Status,
+        Icon::Training,
+        Icon::Attention,
+    ];
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Food => "food",
+            Self::Light => "light",
+            Self::Game => "game",
+            Self::Medicine => "med",
+            Self::Bathroom => "bath",
+            Self::Status => "status",
+            Self::Training => "train",
+            Self::Attention => "attn",
+        }
+    }
+}
+
+/// One emulated device. Any number can exist at once; they take turns on
+/// tamalib's single CPU (see `instance`), so each behaves as if it had the
+/// library to itself.
 pub struct TamaEngine {
+    id: u64,
+    /// The ROM padded to `PC_SPACE_WORDS`; the first `rom_len` words are
+    /// the ones loaded.
     rom_words: Vec<u16>,
+    rom_len: usize,
+    hal: hal::HalContext,
+    breakpoints: debug::BreakpointList,
+    tracer: trace::Tracer,
+    /// Held buttons, replayed when the engine's CPU is swapped back in.
+    buttons: [bool; BUTTONS.len()],
+    /// Pet age carried over from a loaded snapshot, and `emulated_ticks`
+    /// when it was loaded.
+    age_base_ticks: u64,
+    age_origin_ticks: u64,
+    paused: bool,
     initialized: bool,
 }
 
+/// tamalib fetches from anywhere the 13-bit PC can reach, so the ROM is
+/// padded with zero words to cover all of it rather than read past its end.
+fn padded_rom(mut rom_words: Vec<u16>) -> Vec<u16> {
+    if rom_words.len() < crate::rom::PC_SPACE_WORDS {
+        rom_words.resize(crate::rom::PC_SPACE_WORDS, 0);
+    }
+    rom_words
+}
+
 impl TamaEngine {
     pub fn save_snapshot(&self) -> Snapshot {
-        unsafe {
-            let state_ptr = ffi::cpu_get_state();
-            let state = &*state_ptr;
-
-            let mut interrupts = Vec::with_capacity(INT_SLOT_NUM);
-            for i in 0..INT_SLOT_NUM {
-                let intr = *state.interrupts.add(i);
-                interrupts.push(SnapshotInterrupt::from(intr));
-            }
-
-            let mut memory = vec![0u8; MEM_BUFFER_SIZE];
-            std::ptr::copy_nonoverlapping(state.memory, memory.as_mut_ptr(), MEM_BUFFER_SIZE);
-
-            Snapshot {
-                pc: *state.pc,
-                x: *state.x,
-                y: *state.y,
-                a: *state.a,
-                b: *state.b,
-                np: *state.np,
-                sp: *state.sp,
-                flags: *state.flags,
-                tick_counter: *state.tick_counter,
-                clk_timer_2hz_timestamp: *state.clk_timer_2hz_timestamp,
-                clk_timer_4hz_timestamp: *state.clk_timer_4hz_timestamp,
-                clk_timer_8hz_timestamp: *state.clk_timer_8hz_timestamp,
-                clk_timer_16hz_timestamp: *state.clk_timer_16hz_timestamp,
-                clk_timer_32hz_timestamp: *state.clk_timer_32hz_timestamp,
-                clk_timer_64hz_timestamp: *state.clk_timer_64hz_timestamp,
-                clk_timer_128hz_timestamp: *state.clk_timer_128hz_timestamp,
-                clk_timer_256hz_timestamp: *state.clk_timer_256hz_timestamp,
-                prog_timer_timestamp: *state.prog_timer_timestamp,
-                prog_timer_enabled: *state.prog_timer_enabled,
-                prog_timer_data: *state.prog_timer_data,
-                prog_timer_rld: *state.prog_timer_rld,
-                call_depth: *state.call_depth,
-                interrupts,
-                cpu_halted: *state.cpu_halted,
-                memory,
-            }
+        let _active = instance::activate(self);
+        Snapshot {
+            saved_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            peripherals: Peripherals {
+                buttons: self.buttons,
+            },
+            age_ticks: self.pet_age_ticks(),
+            ..instance::capture_cpu()
         }
     }
 
+    /// Restores CPU, memory and held buttons, then has tamalib re-derive its
+    /// hardware outputs from the restored I/O registers, so the screen and
+    /// buzzer match the save even if the ROM does not redraw for a while.
     pub fn load_snapshot(&mut self, snap: &Snapshot) {
+        let _active = instance::activate(self);
+        self.hal.sync();
+        instance::restore_cpu(snap);
+        self.hal.rebase_ticks();
+        self.age_base_ticks = snap.age_ticks;
+        self.age_origin_ticks = self.hal.emulated_ticks();
+        for (index, &pressed) in snap.peripherals.buttons.iter().enumerate() {
+            self.set_button(BUTTONS[index], pressed);
+        }
         unsafe {
-            let state_ptr = ffi::cpu_get_state();
-            let state = &*state_ptr;
-
-            *state.pc = snap.pc;
-            *state.x = snap.x;
-            *state.y = snap.y;
-            *state.a = snap.a;
-            *state.b = snap.b;
-            *state.np = snap.np;
-            *state.sp = snap.sp;
-            *state.flags = snap.flags;
-            *state.tick_counter = snap.tick_counter;
-            *state.clk_timer_2hz_timestamp = snap.clk_timer_2hz_timestamp;
-            *state.clk_timer_4hz_timestamp = snap.clk_timer_4hz_timestamp;
-            *state.clk_timer_8hz_timestamp = snap.clk_timer_8hz_timestamp;
-            *state.clk_timer_16hz_timestamp = snap.clk_timer_16hz_timestamp;
-            *state.clk_timer_32hz_timestamp = snap.clk_timer_32hz_timestamp;
-            *state.clk_timer_64hz_timestamp = snap.clk_timer_64hz_timestamp;
-            *state.clk_timer_128hz_timestamp = snap.clk_timer_128hz_timestamp;
-            *state.clk_timer_256hz_timestamp = snap.clk_timer_256hz_timestamp;
-            *state.prog_timer_timestamp = snap.prog_timer_timestamp;
-            *state.prog_timer_enabled = snap.prog_timer_enabled;
-            *state.prog_timer_data = snap.prog_timer_data;
-            *state.prog_timer_rld = snap.prog_timer_rld;
-            *state.call_depth = snap.call_depth;
-            *state.cpu_halted = snap.cpu_halted;
-
-            for i in 0..INT_SLOT_NUM.min(snap.interrupts.len()) {
-                *state.interrupts.add(i) = ffi::Interrupt::from(snap.interrupts[i]);
-            }
-
-            let copy_len = MEM_BUFFER_SIZE.min(snap.memory.len());
-            std::ptr::copy_nonoverlapping(snap.memory.as_ptr(), state.memory, copy_len);
+            ffi::cpu_refresh_hw();
         }
+        self.hal.redraw();
     }
 
     pub fn new(rom_words: Vec<u16>) -> Result<Self, EngineError> {
-        hal::install_hal();
+        Self::with_clock(rom_words, ClockMode::Wall)
+    }
 
-        let init_result =
-            unsafe { ffi::tamalib_init(rom_words.as_ptr(), std::ptr::null_mut(), 1_000_000) };
+    pub fn with_clock(rom_words: Vec<u16>, clock: ClockMode) -> Result<Self, EngineError> {
+        Self::with_config(
+            rom_words,
+            EngineConfig {
+                clock,
+                ..EngineConfig::default()
+            },
+        )
+    }
+
+    pub fn with_config(rom_words: Vec<u16>, config: EngineConfig) -> Result<Self, EngineError> {
+        if config.oscillator_hz == 0 {
+            return Err(EngineError::InvalidOscillator(config.oscillator_hz));
+        }
+        let clock = config.clock;
+        let hal = hal::HalContext::new(clock, config.oscillator_hz);
+        hal.register();
+
+        let mut engine = Self {
+            id: instance::next_id(),
+            rom_len: rom_words.len(),
+            rom_words: padded_rom(rom_words),
+            hal,
+            breakpoints: debug::BreakpointList::new(),
+            tracer: trace::Tracer::new(0),
+            buttons: [false; BUTTONS.len()],
+            age_base_ticks: 0,
+            age_origin_ticks: 0,
+            paused: false,
+            initialized: false,
+        };
+
+        let mut active = instance::begin_init(&engine);
+        let init_result = unsafe {
+            ffi::tamalib_init(
+                engine.rom_words.as_ptr(),
+                engine.breakpoints.head_ptr(),
+                TIMESTAMP_FREQUENCY,
+            )
+        };
 
         if init_result != 0 {
             return Err(EngineError::InitFailed);
         }
+        instance::claim(&mut active, &engine);
+        engine.hal.reset_clock();
 
         unsafe {
+            if clock == ClockMode::Virtual {
+                // Skip tamalib's sleep_until pacing entirely.
+                ffi::cpu_set_speed(0);
+            }
             ffi::tamalib_set_exec_mode(ffi::ExecMode::Run);
         }
+        drop(active);
 
-        Ok(Self {
-            rom_words,
-            initialized: true,
-        })
+        engine.initialized = true;
+        Ok(engine)
     }
 
-    pub fn tick(&mut self) {
+    /// Cold-boots the CPU, like pulling the battery: registers, RAM, timers
+    /// and the screen start over from the reset vector. Breakpoints, held
+    /// buttons and the emulated clock carry on.
+    pub fn reset(&mut self) -> Result<(), EngineError> {
         if !self.initialized {
-            return;
+            return Err(EngineError::InitFailed);
+        }
+        let _active = instance::activate(self);
+        self.hal.sync();
+        self.hal.power_cycle();
+        self.paused = false;
+        if !instance::cold_boot(self) {
+            self.initialized = false;
+            return Err(EngineError::InitFailed);
+        }
+        self.hal.rebase_ticks();
+        self.age_base_ticks = 0;
+        self.age_origin_ticks = self.hal.emulated_ticks();
+        Ok(())
+    }
+
+    /// Swaps in a new ROM and cold-boots it; the old program's state means
+    /// nothing to the new one.
+    pub fn reload_rom(&mut self, rom_words: Vec<u16>) -> Result<(), EngineError> {
+        self.rom_len = rom_words.len();
+        self.rom_words = padded_rom(rom_words);
+        self.tracer.clear();
+        self.reset()
+    }
+
+    /// Executes one instruction. Returns why execution stopped, if it did;
+    /// once stopped, further ticks are no-ops until [`Self::resume`].
+    pub fn tick(&mut self) -> Option<StopReason> {
+        let _active = instance::activate(self);
+        self.tick_inner()
+    }
+
+    fn tick_inner(&mut self) -> Option<StopReason> {
+        if !self.initialized || self.paused {
+            return None;
+        }
+        self.step_cpu();
+
+        if self.breakpoints.is_empty() {
+            return None;
+        }
+        let pc = self.state()?.pc;
+        if self.breakpoints.contains(pc) {
+            self.set_paused(true);
+            return Some(StopReason::Breakpoint { pc });
+        }
+        None
+    }
+
+    pub fn tick_many(&mut self, steps: usize) -> Option<StopReason> {
+        let _active = instance::activate(self);
+        let mut stop = None;
+        for _ in 0..steps {
+            stop = self.tick_inner();
+            if stop.is_some() || self.paused {
+                break;
+            }
+        }
+        self.hal.sync();
+        stop
+    }
+
+    fn step_cpu(&mut self) {
+        if !self.tracer.is_idle()
+            && let Some(state) = self.state()
+        {
+            let opcode = self.rom_words.get(state.pc as usize).copied().unwrap_or(0);
+            self.tracer.record(trace::TraceEntry {
+                pc: state.pc,
+                opcode,
+                state,
+            });
         }
         unsafe {
             ffi::tamalib_step();
         }
     }
 
-    pub fn tick_many(&mut self, steps: usize) {
-        for _ in 0..steps {
-            self.tick();
+    /// Keeps the last `capacity` executed instructions; 0 turns it off.
+    pub fn set_trace_capacity(&mut self, capacity: usize) {
+        self.tracer.set_capacity(capacity);
+    }
+
+    /// Recorded instructions, oldest first.
+    pub fn trace(&self) -> Vec<TraceEntry> {
+        self.tracer.entries()
+    }
+
+    pub fn clear_trace(&mut self) {
+        self.tracer.clear();
+    }
+
+    /// Streams one line per executed instruction to `path`, replacing any
+    /// previous stream.
+    pub fn stream_trace_to(&mut self, path: &Path) -> std::io::Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        self.tracer.set_stream(Some(Box::new(file)))
+    }
+
+    /// Flushes and closes the trace stream, reporting any write error that
+    /// stopped it early.
+    pub fn stop_trace_stream(&mut self) -> std::io::Result<()> {
+        self.tracer.set_stream(None)
+    }
+
+    pub fn add_breakpoint(&mut self, pc: u16) -> bool {
+        self.breakpoints.insert(pc)
+    }
+
+    pub fn remove_breakpoint(&mut self, pc: u16) -> bool {
+        self.breakpoints.remove(pc)
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn breakpoints(&self) -> Vec<u16> {
+        self.breakpoints.addrs()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Continues free-running execution after a stop.
+    pub fn resume(&mut self) {
+        self.set_paused(false);
+    }
+
+    /// Routes this engine's tamalib log output for `levels` to `sink`,
+    /// replacing any previously registered one.
+    pub fn set_log_sink<F>(&mut self, levels: &[LogLevel], sink: F)
+    where
+        F: FnMut(LogLevel, &str) + 'static,
+    {
+        self.hal
+            .set_log_sink(LogLevel::mask(levels), Some(Box::new(sink)));
+    }
+
+    pub fn set_log_levels(&mut self, levels: &[LogLevel]) {
+        self.hal.set_log_mask(LogLevel::mask(levels));
+    }
+
+    pub fn clear_log_sink(&mut self) {
+        self.hal.set_log_sink(0, None);
+    }
+
+    /// Reads one nibble of the CPU data space (RAM, display or I/O).
+    pub fn read_mem(&self, addr: u16) -> Result<u8, MemoryError> {
+        let _active = instance::activate(self);
+        memory::read_nibble(self.memory(), addr)
+    }
+
+    /// Writes one nibble. Display and I/O writes land in memory only; they
+    /// do not trigger the side effects a CPU write would.
+    pub fn write_mem(&mut self, addr: u16, value: u8) -> Result<(), MemoryError> {
+        let _active = instance::activate(self);
+        memory::write_nibble(self.memory_mut(), addr, value)
+    }
+
+    pub fn read_mem_range(&self, addr: u16, len: usize) -> Result<Vec<u8>, MemoryError> {
+        memory::check_range(addr, len)?;
+        (0..len)
+            .map(|offset| self.read_mem(addr + offset as u16))
+            .collect()
+    }
+
+    /// Writes `values` starting at `addr`; nothing is written unless the
+    /// whole range is mapped and every value fits in a nibble.
+    pub fn write_mem_range(&mut self, addr: u16, values: &[u8]) -> Result<(), MemoryError> {
+        memory::check_range(addr, values.len())?;
+        for (offset, &value) in values.iter().enumerate() {
+            if value > 0x0F {
+                return Err(MemoryError::InvalidNibble {
+                    addr: addr + offset as u16,
+                    value,
+                });
+            }
         }
+        for (offset, &value) in values.iter().enumerate() {
+            self.write_mem(addr + offset as u16, value)?;
+        }
+        Ok(())
+    }
+
+    /// Decodes the pet's stats from live RAM using `layout`.
+    pub fn pet_status(&self, layout: &crate::pet::RamLayout) -> crate::pet::PetStatus {
+        let _active = instance::activate(self);
+        crate::pet::PetStatus::decode(self.memory(), layout)
+    }
+
+    /// Callers must hold this engine's [`instance::Active`] guard.
+    fn memory(&self) -> &[u8] {
+        unsafe {
+            let state = &*ffi::cpu_get_state();
+            std::slice::from_raw_parts(state.memory, MEM_BUFFER_SIZE)
+        }
+    }
+
+    fn memory_mut(&mut self) -> &mut [u8] {
+        unsafe {
+            let state = &*ffi::cpu_get_state();
+            std::slice::from_raw_parts_mut(state.memory, MEM_BUFFER_SIZE)
+        }
+    }
+
+    /// Stops free-running execution and returns the CPU state at that point.
+    pub fn pause(&mut self) -> Option<CpuStateView> {
+        self.set_paused(true);
+        self.state()
+    }
+
+    /// Executes exactly one instruction, entering calls.
+    pub fn step_into(&mut self) -> Option<CpuStateView> {
+        self.run_exec_mode(ffi::ExecMode::Step)
+    }
+
+    /// Executes one instruction, running any call it makes to completion.
+    pub fn step_over(&mut self) -> Option<CpuStateView> {
+        self.run_exec_mode(ffi::ExecMode::Next)
+    }
+
+    /// Runs until the next call enters a deeper subroutine.
+    pub fn run_to_call(&mut self) -> Option<CpuStateView> {
+        self.run_exec_mode(ffi::ExecMode::ToCall)
+    }
+
+    /// Runs until the current subroutine returns to its caller.
+    pub fn run_to_return(&mut self) -> Option<CpuStateView> {
+        self.run_exec_mode(ffi::ExecMode::ToRet)
+    }
+
+    /// Drives tamalib in one of its debugger modes. tamalib pauses itself
+    /// when the mode completes but offers no way to query that, so the same
+    /// call-depth conditions are mirrored here to know when to stop. Hitting
+    /// a breakpoint or `DEBUG_STEP_LIMIT` also ends the command; the engine
+    /// is left paused either way.
+    fn run_exec_mode(&mut self, mode: ffi::ExecMode) -> Option<CpuStateView> {
+        if !self.initialized {
+            return None;
+        }
+
+        let _active = instance::activate(self);
+        let start_depth = self.state()?.call_depth;
+        self.paused = false;
+        unsafe {
+            ffi::tamalib_set_exec_mode(mode);
+        }
+
+        for _ in 0..debug::DEBUG_STEP_LIMIT {
+            self.step_cpu();
+            let Some(view) = self.state() else {
+                break;
+            };
+            let done = match mode {
+                ffi::ExecMode::Next => view.call_depth <= start_depth,
+                ffi::ExecMode::ToCall => view.call_depth > start_depth,
+                ffi::ExecMode::ToRet => view.call_depth < start_depth,
+                ffi::ExecMode::Pause | ffi::ExecMode::Run | ffi::ExecMode::Step => true,
+            };
+            if done || self.breakpoints.contains(view.pc) {
+                break;
+            }
+        }
+
+        self.set_paused(true);
+        self.hal.sync();
+        self.state()
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        let mode = if paused {
+            ffi::ExecMode::Pause
+        } else {
+            ffi::ExecMode::Run
+        };
+        let _active = instance::activate(self);
+        unsafe {
+            ffi::tamalib_set_exec_mode(mode);
+        }
+    }
+
+    /// Runs until `span` of emulated time has elapsed, as fast as the host
+    /// allows. `progress` receives the emulated ticks done and the total.
+    /// Returns the emulated ticks actually run, which is short of the target
+    /// only if the CPU stops advancing.
+    pub fn fast_forward<F: FnMut(u64, u64)>(&mut self, span: Duration, progress: F) -> u64 {
+        let total = self.duration_to_ticks(span);
+        self.run_ticks(total, progress)
+    }
+
+    /// Runs at least `total` emulated ticks (overshooting by less than one
+    /// instruction), reporting progress like [`Self::fast_forward`].
+    pub fn run_ticks<F: FnMut(u64, u64)>(&mut self, total: u64, mut progress: F) -> u64 {
+        const CHUNK_STEPS: u64 = 50_000;
+        // The slowest E0C6S46 instruction takes 12 ticks, so this many steps
+        // never overshoot the remaining span by more than one instruction.
+        const MAX_TICKS_PER_STEP: u64 = 12;
+
+        let start = self.emulated_ticks();
+        let mut done = 0;
+        while done < total {
+            let steps = ((total - done) / MAX_TICKS_PER_STEP).clamp(1, CHUNK_STEPS);
+            self.tick_many(steps as usize);
+            let now = self.emulated_ticks() - start;
+            if now == done {
+                break;
+            }
+            done = now.min(total);
+            progress(done, total);
+        }
+        done
     }
 
     pub fn set_button(&mut self, button: Button, pressed: bool) {
@@ -152,29 +571,97 @@ impl TamaEngine {
         } else {
             ffi::ButtonState::Released
         };
+        self.buttons[button as usize] = pressed;
+        let _active = instance::activate(self);
         unsafe {
             ffi::hw_set_button(button, state);
         }
     }
 
     pub fn state(&self) -> Option<CpuStateView> {
+        let _active = instance::activate(self);
         let ptr = unsafe { ffi::cpu_get_state() };
         CpuStateView::from_raw(ptr)
     }
 
     pub fn get_lcd(&self) -> [[bool; 32]; 16] {
-        hal::get_lcd_matrix()
+        self.hal.lcd_matrix()
+    }
+
+    /// Returns the latest latched frame if it is newer than the last one
+    /// returned, so renderers can skip unchanged screens.
+    pub fn poll_frame(&mut self) -> Option<Frame> {
+        self.hal.poll_frame()
+    }
+
+    pub fn get_icons(&self) -> [bool; ICON_COUNT] {
+        self.hal.lcd_icons()
+    }
+
+    pub fn icon(&self, icon: Icon) -> bool {
+        self.get_icons()[icon.index()]
+    }
+
+    /// Returns buzzer events recorded since the last call, oldest first.
+    pub fn drain_sound_events(&mut self) -> Vec<SoundEvent> {
+        self.hal.drain_sound_events()
+    }
+
+    /// Current buzzer frequency (decihertz) and whether it is sounding.
+    pub fn buzzer(&self) -> (u32, bool) {
+        self.hal.buzzer_state()
+    }
+
+    /// Emulated CPU ticks executed since the engine was created.
+    pub fn emulated_ticks(&self) -> u64 {
+        let _active = instance::activate(self);
+        self.hal.emulated_ticks()
+    }
+
+    /// Emulated time since the engine was created, at its oscillator rate.
+    pub fn emulated_time(&self) -> Duration {
+        self.ticks_to_duration(self.emulated_ticks())
+    }
+
+    /// Emulated ticks since the pet's cold boot, including earlier sessions
+    /// restored from a snapshot.
+    pub fn pet_age_ticks(&self) -> u64 {
+        self.age_base_ticks + (self.emulated_ticks() - self.age_origin_ticks)
     }
 
+    pub fn pet_age(&self) -> Duration {
+        self.ticks_to_duration(self.pet_age_ticks())
+    }
+
+    /// Emulated time spanned by `ticks`, e.g. a `SoundEvent::tick`, at
+    /// this engine's oscillator rate.
+    pub fn ticks_to_duration(&self, ticks: u64) -> Duration {
+        sound::ticks_to_duration(ticks, self.oscillator_hz())
+    }
+
+    pub fn oscillator_hz(&self) -> u32 {
+        self.hal.oscillator_hz()
+    }
+
+    pub fn duration_to_ticks(&self, span: Duration) -> u64 {
+        (span.as_nanos() * self.oscillator_hz() as u128 / 1_000_000_000) as u64
+    }
+
+    /// Length of the ROM as loaded, before padding.
     pub fn rom_len_words(&self) -> usize {
-        self.rom_words.len()
+        self.rom_len
+    }
+
+    /// The ROM as loaded, before padding.
+    pub fn rom(&self) -> &[u16] {
+        &self.rom_words[..self.rom_len]
     }
 }
 
 impl Drop for TamaEngine {
     fn drop(&mut self) {
         if self.initialized {
-            unsafe { ffi::tamalib_release() };
+            instance::release(self);
             self.initialized = false;
         }
     }
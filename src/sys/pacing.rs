@@ -0,0 +1,84 @@
+use super::TamaEngine;
+use std::time::{Duration, Instant};
+
+/// Wall time the pacer makes up in one go. A bigger backlog (the host was
+/// suspended, or the engine sat at a breakpoint) is dropped instead of
+/// replayed, so the UI does not freeze while the engine sprints.
+pub const MAX_LAG: Duration = Duration::from_secs(1);
+
+/// Fastest supported speed multiplier. Far above what the CPU can keep up
+/// with, and low enough that scaled durations cannot overflow.
+pub const MAX_SPEED: f64 = 1_000.0;
+
+/// Keeps an engine's emulated time locked to wall time times `speed`.
+///
+/// Every `advance` runs as many ticks as are due since a fixed origin rather
+/// than a fixed batch per loop iteration, so rounding never accumulates and
+/// the pet's clock keeps time regardless of host speed or loop jitter.
+pub struct Pacer {
+    speed: f64,
+    origin: Instant,
+    origin_ticks: u64,
+    dropped: Duration,
+}
+
+impl Pacer {
+    pub fn new(engine: &TamaEngine, speed: f64) -> Self {
+        Self {
+            speed: clamp_speed(speed),
+            origin: Instant::now(),
+            origin_ticks: engine.emulated_ticks(),
+            dropped: Duration::ZERO,
+        }
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, engine: &TamaEngine, speed: f64) {
+        self.speed = clamp_speed(speed);
+        self.resync(engine);
+    }
+
+    /// Treats the engine's current time as "now", e.g. after it was run
+    /// outside the pacer or had a snapshot loaded.
+    pub fn resync(&mut self, engine: &TamaEngine) {
+        self.origin = Instant::now();
+        self.origin_ticks = engine.emulated_ticks();
+    }
+
+    /// Runs the engine up to the tick wall time says it should be at.
+    /// Returns the ticks run.
+    pub fn advance(&mut self, engine: &mut TamaEngine) -> u64 {
+        let elapsed = self.origin.elapsed().mul_f64(self.speed);
+        let due = self.origin_ticks + engine.duration_to_ticks(elapsed);
+        let now = engine.emulated_ticks();
+        if due <= now {
+            return 0;
+        }
+
+        let behind = due - now;
+        if behind > engine.duration_to_ticks(MAX_LAG.mul_f64(self.speed)) {
+            let hz = engine.oscillator_hz() as f64;
+            self.dropped += Duration::from_secs_f64(behind as f64 / hz / self.speed);
+            self.resync(engine);
+            return 0;
+        }
+        engine.run_ticks(behind, |_, _| {})
+    }
+
+    /// Wall time skipped because the engine fell more than [`MAX_LAG`] behind.
+    pub fn dropped(&self) -> Duration {
+        self.dropped
+    }
+}
+
+/// Limits `speed` to `(0, MAX_SPEED]`; NaN becomes 1.
+fn clamp_speed(speed: f64) -> f64 {
+    if speed.is_nan() {
+        1.0
+    } else {
+        speed.clamp(f64::MIN_POSITIVE, MAX_SPEED)
+    }
+}
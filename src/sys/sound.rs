@@ -0,0 +1,33 @@
+use std::time::Duration;
+
+pub const SOUND_QUEUE_CAPACITY: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundEventKind {
+    /// Buzzer frequency changed, in decihertz as reported by tamalib.
+    Frequency(u32),
+    Start,
+    Stop,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SoundEvent {
+    /// Emulated CPU ticks since the engine was created, at the engine's
+    /// oscillator rate.
+    pub tick: u64,
+    pub kind: SoundEventKind,
+}
+
+impl SoundEvent {
+    /// When the event happened, for an engine whose oscillator runs at
+    /// `oscillator_hz` (see `TamaEngine::oscillator_hz`).
+    pub fn time(&self, oscillator_hz: u32) -> Duration {
+        ticks_to_duration(self.tick, oscillator_hz)
+    }
+}
+
+/// Emulated time spanned by `ticks` at `oscillator_hz`.
+pub fn ticks_to_duration(ticks: u64, oscillator_hz: u32) -> Duration {
+    let hz = oscillator_hz.max(1) as u64;
+    Duration::from_secs(ticks / hz) + Duration::from_nanos((ticks % hz) * 1_000_000_000 / hz)
+}
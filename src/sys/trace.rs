@@ -0,0 +1,89 @@
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+
+use super::ffi::CpuStateView;
+
+/// One executed instruction, with the registers as they were before it ran.
+#[derive(Debug, Clone, Copy)]
+pub struct TraceEntry {
+    pub pc: u16,
+    pub opcode: u16,
+    pub state: CpuStateView,
+}
+
+impl Display for TraceEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = &self.state;
+        write!(
+            f,
+            "{:08X} {:04X}: {:03X}  A={:X} B={:X} X={:03X} Y={:03X} SP={:02X} NP={:02X} F={:X}",
+            s.tick_counter, self.pc, self.opcode, s.a, s.b, s.x, s.y, s.sp, s.np, s.flags
+        )
+    }
+}
+
+pub struct Tracer {
+    ring: VecDeque<TraceEntry>,
+    capacity: usize,
+    stream: Option<Box<dyn Write + Send>>,
+    error: Option<std::io::Error>,
+}
+
+impl Tracer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            ring: VecDeque::with_capacity(capacity),
+            capacity,
+            stream: None,
+            error: None,
+        }
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.ring.len() > capacity {
+            self.ring.pop_front();
+        }
+    }
+
+    pub fn set_stream(&mut self, stream: Option<Box<dyn Write + Send>>) -> std::io::Result<()> {
+        let previous = std::mem::replace(&mut self.stream, stream);
+        if let Some(mut previous) = previous {
+            previous.flush()?;
+        }
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.capacity == 0 && self.stream.is_none()
+    }
+
+    pub fn record(&mut self, entry: TraceEntry) {
+        if self.capacity > 0 {
+            if self.ring.len() == self.capacity {
+                self.ring.pop_front();
+            }
+            self.ring.push_back(entry);
+        }
+
+        if let Some(stream) = self.stream.as_mut()
+            && let Err(err) = writeln!(stream, "{entry}")
+        {
+            // Stop streaming; the error surfaces when the stream is replaced.
+            self.stream = None;
+            self.error = Some(err);
+        }
+    }
+
+    pub fn entries(&self) -> Vec<TraceEntry> {
+        self.ring.iter().copied().collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.ring.clear();
+    }
+}
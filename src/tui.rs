@@ -6,6 +6,10 @@ use crossterm::{
 };
 use std::io::{stdout, Write};
 
+use crate::sys::{Icon, ICON_COUNT};
+
+const ICON_CELL_WIDTH: usize = 8;
+
 pub struct TuiRenderer {
     stdout: std::io::Stdout,
 }
@@ -19,8 +23,14 @@ impl TuiRenderer {
         Ok(Self { stdout })
     }
 
-    pub fn render(&mut self, lcd: &[[bool; 32]; 16]) -> std::io::Result<()> {
+    pub fn render(
+        &mut self,
+        lcd: &[[bool; 32]; 16],
+        icons: &[bool; ICON_COUNT],
+    ) -> std::io::Result<()> {
         self.stdout.queue(cursor::MoveTo(0, 0))?;
+        self.stdout.queue(Clear(ClearType::CurrentLine))?;
+        self.render_icon_row(&Icon::ALL[..4], icons)?;
 
         self.stdout
             .queue(Print("┌────────────────────────────────┐\r\n"))?;
@@ -40,9 +50,34 @@ impl TuiRenderer {
         self.stdout
             .queue(Print("└────────────────────────────────┘\r\n"))?;
 
+        self.stdout.queue(Clear(ClearType::CurrentLine))?;
+        self.render_icon_row(&Icon::ALL[4..], icons)?;
+
         self.stdout.flush()?;
         Ok(())
     }
+
+    fn render_icon_row(&mut self, row: &[Icon], icons: &[bool; ICON_COUNT]) -> std::io::Result<()> {
+        self.stdout.queue(Print(" "))?;
+        for &icon in row {
+            let label = format!("{:^width$}", icon.label(), width = ICON_CELL_WIDTH);
+            if icons[icon.index()] {
+                self.stdout
+                    .queue(SetBackgroundColor(Color::White))?
+                    .queue(SetForegroundColor(Color::Black))?;
+            } else {
+                self.stdout
+                    .queue(SetBackgroundColor(Color::Reset))?
+                    .queue(SetForegroundColor(Color::DarkGrey))?;
+            }
+            self.stdout.queue(Print(label))?;
+        }
+        self.stdout
+            .queue(SetBackgroundColor(Color::Reset))?
+            .queue(SetForegroundColor(Color::Reset))?
+            .queue(Print("\r\n"))?;
+        Ok(())
+    }
 }
 
 impl Drop for TuiRenderer {